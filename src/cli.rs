@@ -1,115 +1,976 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
 use std::num::{ParseFloatError, ParseIntError};
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use clap::Parser;
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser};
+use clap::{Arg, ArgEnum, ArgGroup, ArgMatches, Args, FromArgMatches, Parser};
 use thiserror::Error as ThisError;
 
 use crate::domain;
 use crate::domain::{
-    allegiance, exclude_permit_locked, exclude_player_faction, exclude_rare_commodity, government,
-    max_distance_from_reference, max_distance_from_sol, max_number_of_factions, min_docks,
-    min_large_docks, min_population, min_starports, System,
+    allegiance_one_of, and, controlled_by, controlling_faction_allegiance,
+    controlling_faction_government, economy, exclude_allegiances, exclude_governments,
+    exclude_permit_locked, exclude_player_controlled, exclude_player_faction,
+    exclude_rare_commodity, faction_absent, faction_in_state, faction_present, government_one_of,
+    max_controlling_faction_influence, max_controlling_faction_lead, max_distance_from_reference,
+    max_distance_from_sol, max_distance_to_arrival, max_fleet_carriers, max_number_of_factions,
+    max_population, min_distance_from_reference, min_distance_from_sol, min_docks,
+    min_docks_within, min_faction_influence, min_fleet_carriers, min_large_docks,
+    min_large_docks_within, min_number_of_factions, min_population, min_starports,
+    min_starports_within, min_stations, min_stations_with_service, min_stations_within, or,
+    require_service, security, sort_by, station_matching, station_with_service, system_state,
+    Allegiance, Coords, Economy, Government, PadSize, Security, Sort, SortField, SortOrder,
+    StateStage, StationCriteria, StationFilter, System,
 };
+use crate::output::Format;
+use crate::query;
 
 #[derive(Parser, Debug, Default, PartialEq)]
 #[clap(author, version, about)]
+#[clap(group = ArgGroup::new("docks").multiple(true))]
+#[clap(group = ArgGroup::new("uses-reference").multiple(true))]
+#[clap(group = ArgGroup::new("reference-point"))]
 pub struct Cli {
     /// This is the path to th EDSM dump in .json.gz format
     #[clap(value_name = "edsm-path")]
     pub edsm_path: PathBuf,
     /// Filter the systems that are have less than the given number of docks with room for large ships
-    #[clap(long, value_name = "COUNT")]
+    #[clap(long, value_name = "COUNT", group = "docks")]
     min_docks_large: Option<usize>,
-    /// Filter by allegiance
-    #[clap(long, value_name = "MAJOR_FACTION")]
-    allegiance: Option<String>,
-    /// Filter by government
-    #[clap(long, value_name = "GOVERNMENT_TYPE")]
-    government: Option<String>,
+    /// Filter by allegiance, separate several with commas to allow any of them
+    #[clap(long, value_name = "MAJOR_FACTION", value_parser = KnownValues::<Allegiance>::new(Allegiance::names()), use_value_delimiter = true, multiple_occurrences = true)]
+    allegiance: Vec<Allegiance>,
+    /// Filter out these allegiances, separated by commas
+    #[clap(long, value_name = "MAJOR_FACTION", value_parser = KnownValues::<Allegiance>::new(Allegiance::names()), use_value_delimiter = true, multiple_occurrences = true)]
+    not_allegiance: Vec<Allegiance>,
+    /// Filter by government, separate several with commas to allow any of them
+    #[clap(long, value_name = "GOVERNMENT_TYPE", value_parser = KnownValues::<Government>::new(Government::names()), use_value_delimiter = true, multiple_occurrences = true)]
+    government: Vec<Government>,
+    /// Filter out these governments, separated by commas
+    #[clap(long, value_name = "GOVERNMENT_TYPE", value_parser = KnownValues::<Government>::new(Government::names()), use_value_delimiter = true, multiple_occurrences = true)]
+    not_government: Vec<Government>,
+    /// Filter by the system's primary economy
+    #[clap(long, value_name = "ECONOMY", value_parser = KnownValues::<Economy>::new(Economy::names()))]
+    economy: Option<Economy>,
+    /// Filter by security level
+    #[clap(long, value_name = "SECURITY", value_parser = KnownValues::<Security>::new(Security::names()))]
+    security: Option<Security>,
+    /// Filter by the system's state
+    #[clap(long, value_name = "STATE")]
+    system_state: Option<String>,
     /// Filter the systems that are have less than the given number of starports
-    #[clap(long, value_name = "COUNT")]
+    #[clap(long, value_name = "COUNT", group = "docks")]
     min_starports: Option<usize>,
     /// Filter the systems that are have less than the given number of docks
-    #[clap(long, value_name = "COUNT")]
+    #[clap(long, value_name = "COUNT", group = "docks")]
     min_docks: Option<usize>,
+    /// Filter the systems with fewer stations matching the criteria than the count, criteria are
+    /// any of pad=small|medium|large, orbital=true|false and permanent=true|false separated by
+    /// commas, like pad=large,orbital=true,permanent=true:3, can be given more than once
+    #[clap(
+        long,
+        value_name = "CRITERIA:COUNT",
+        group = "docks",
+        multiple_occurrences = true
+    )]
+    min_stations: Vec<StationCount>,
+    /// Filter the systems without a station offering this service, like Material Trader or
+    /// Shipyard, can be given more than once
+    #[clap(long, value_name = "SERVICE", multiple_occurrences = true)]
+    require_service: Vec<String>,
+    /// Filter the systems with fewer stations offering a service than the number given, like
+    /// Shipyard:2, can be given more than once
+    #[clap(long, value_name = "SERVICE:COUNT", multiple_occurrences = true)]
+    min_stations_with_service: Vec<ServiceCount>,
+    /// Filter the systems with fewer fleet carriers than the number given
+    #[clap(long, value_name = "COUNT")]
+    min_fleet_carriers: Option<usize>,
+    /// Filter the systems with more fleet carriers than the number given, 0 skips any system with a
+    /// fleet carrier
+    #[clap(long, value_name = "COUNT")]
+    max_fleet_carriers: Option<usize>,
+    /// Only count docks within this distance of the arrival star towards the dock filters
+    #[clap(long, value_name = "LIGHT_SECONDS", requires = "docks")]
+    dock_max_distance_to_arrival: Option<f64>,
     /// Filter the systems that are have less than the given population
     #[clap(long, value_name = "COUNT")]
     min_population: Option<u128>,
+    /// Filter the systems that have more than the given population
+    #[clap(long, value_name = "COUNT")]
+    max_population: Option<u128>,
+    /// Filter the systems with a population outside this range, like 1000..50000, 1000.. or
+    /// ..50000, both ends are included
+    #[clap(long, value_name = "MIN..MAX")]
+    population: Option<CountRange<u128>>,
+    /// Filter the systems with a population outside this bracket
+    #[clap(long, arg_enum, value_name = "BRACKET")]
+    population_bracket: Option<PopulationBracket>,
     /// Filter the systems that are further than this distance from sol
-    #[clap(long, value_name = "LIGHT_SECONDS")]
+    #[clap(long, value_name = "LIGHT_YEARS")]
     max_distance_from_sol: Option<f64>,
+    /// Filter the systems that are closer than this distance to sol
+    #[clap(long, value_name = "LIGHT_YEARS")]
+    min_distance_from_sol: Option<f64>,
     /// Filter the systems that are further than this distance from the reference
-    #[clap(long, value_name = "LIGHT_SECONDS", requires = "reference")]
+    #[clap(
+        long,
+        value_name = "LIGHT_YEARS",
+        requires = "reference-point",
+        group = "uses-reference"
+    )]
     max_distance_from_reference: Option<f64>,
+    /// Filter the systems that are closer than this distance to the reference
+    #[clap(
+        long,
+        value_name = "LIGHT_YEARS",
+        requires = "reference-point",
+        group = "uses-reference"
+    )]
+    min_distance_from_reference: Option<f64>,
     /// Filter the systems that have more factions than the number given
     #[clap(long, value_name = "COUNT")]
     max_number_of_factions: Option<usize>,
-    /// A reference system used by other filters
+    /// Filter the systems that have fewer factions than the number given
+    #[clap(long, value_name = "COUNT")]
+    min_number_of_factions: Option<usize>,
+    /// Filter the systems with a number of factions outside this range, like 2..6, 2.. or ..6, both
+    /// ends are included, a single number matches only that many
+    #[clap(long, value_name = "MIN..MAX")]
+    number_of_factions: Option<CountRange<usize>>,
+    /// A reference system used by other filters, either a populated system or one of Sagittarius
+    /// A*, Colonia or Beagle Point
     #[clap(
         long,
         value_name = "SYSTEM_NAME",
-        requires = "max_distance_from_reference"
+        requires = "uses-reference",
+        group = "reference-point"
     )]
     reference: Option<String>,
-    /// Exclude permit locked systems
-    #[clap(long, takes_value = false)]
-    exclude_permit_locked: bool,
-    /// Exclude systems that contain a player faction
-    #[clap(long, takes_value = false)]
-    exclude_player_faction: bool,
-    /// Exclude systems that sell rare commodities
-    #[clap(long, takes_value = false)]
-    exclude_rare_commodity: bool,
-}
-
-pub fn parameters_from_matches<'a, T: System<'a>>(
-    matches: &'a Cli,
-    systems: &'a [T],
-) -> Result<Vec<domain::SystemFilter<'a>>, Error> {
-    let reference = matches
+    /// Coordinates used as the reference by other filters, instead of a reference system
+    #[clap(
+        long,
+        value_name = "X,Y,Z",
+        parse(try_from_str = coords_from_str),
+        allow_hyphen_values = true,
+        requires = "uses-reference",
+        group = "reference-point"
+    )]
+    reference_coords: Option<Coords>,
+    /// Filter the systems that are further than the radius in light years from the named system,
+    /// can be given more than once
+    #[clap(long, value_name = "SYSTEM_NAME:RADIUS", multiple_occurrences = true)]
+    within: Vec<Within>,
+    /// Whether systems must be within all or any of the given radii
+    #[clap(long, arg_enum, value_name = "MODE", default_value = "all")]
+    within_mode: WithinMode,
+    /// Filter the systems with a query combining filters with and, or and not, see the query guide
+    /// for the syntax
+    #[clap(long, value_name = "QUERY", group = "uses-reference")]
+    query: Option<String>,
+    #[clap(flatten)]
+    exclude: Exclusions,
+    /// Filter the systems that aren't controlled by this minor faction
+    #[clap(long, value_name = "FACTION_NAME")]
+    controlling_faction: Option<String>,
+    /// Filter the systems where the controlling faction doesn't have this allegiance
+    #[clap(long, value_name = "MAJOR_FACTION", value_parser = KnownValues::<Allegiance>::new(Allegiance::names()))]
+    controlling_allegiance: Option<Allegiance>,
+    /// Filter the systems where the controlling faction doesn't have this government
+    #[clap(long, value_name = "GOVERNMENT_TYPE", value_parser = KnownValues::<Government>::new(Government::names()))]
+    controlling_government: Option<Government>,
+    /// Filter the systems where this minor faction isn't present, can be given more than once
+    #[clap(long, value_name = "FACTION_NAME", multiple_occurrences = true)]
+    faction_present: Vec<String>,
+    /// Filter the systems where this minor faction is present, can be given more than once
+    #[clap(long, value_name = "FACTION_NAME", multiple_occurrences = true)]
+    faction_absent: Vec<String>,
+    /// Filter the systems where the controlling faction has more than this percentage of the
+    /// influence
+    #[clap(long, value_name = "PERCENT")]
+    max_controlling_influence: Option<f64>,
+    /// Filter the systems where no other faction is within this many percent of the controlling
+    /// faction's influence or ahead of it
+    #[clap(long, value_name = "PERCENT")]
+    max_controlling_lead: Option<f64>,
+    /// Filter the systems where the named faction has less than this percentage of the influence,
+    /// can be given more than once
+    #[clap(long, value_name = "FACTION_NAME:PERCENT", multiple_occurrences = true)]
+    min_faction_influence: Vec<FactionInfluence>,
+    /// Filter the systems without a minor faction in this state, optionally followed by :pending,
+    /// :active or :recovering, can be given more than once
+    #[clap(long, value_name = "STATE[:STAGE]", multiple_occurrences = true)]
+    faction_state: Vec<FactionState>,
+    /// How to display the systems found
+    #[clap(long, arg_enum, value_name = "FORMAT", default_value = "names")]
+    pub output: Format,
+    /// Display a row for each station in the systems found, rather than one for each system
+    #[clap(long)]
+    pub per_station: bool,
+    /// Only display the stations matching these criteria, like pad=large,orbital=true
+    #[clap(
+        long,
+        value_name = "CRITERIA",
+        parse(try_from_str = station_criteria),
+        requires = "per-station"
+    )]
+    station: Option<StationCriteria>,
+    /// Only display the stations offering all of these services
+    #[clap(
+        long,
+        value_name = "SERVICE",
+        use_value_delimiter = true,
+        multiple_occurrences = true,
+        requires = "per-station"
+    )]
+    station_service: Vec<String>,
+    /// Only display the stations within this distance of the arrival star
+    #[clap(long, value_name = "LIGHT_SECONDS", requires = "per-station")]
+    per_station_max_distance_to_arrival: Option<f64>,
+    /// Sort the systems found by one or more of name, distance-from-reference, distance-from-sol,
+    /// population, large-docks or factions, optionally followed by :asc or :desc
+    #[clap(
+        long,
+        value_name = "KEY[:ORDER]",
+        multiple_occurrences = true,
+        use_value_delimiter = true,
+        group = "uses-reference"
+    )]
+    sort_by: Vec<SortKey>,
+    /// Only display this many of the systems found
+    #[clap(long, value_name = "COUNT")]
+    pub limit: Option<usize>,
+    /// Find this many of the systems closest to the reference, not counting the reference itself,
+    /// displaying their distance from it
+    #[clap(
+        long,
+        value_name = "COUNT",
+        requires = "reference-point",
+        group = "uses-reference"
+    )]
+    nearest: Option<usize>,
+    /// Display a row for each minor faction present in the systems found, rather than one for
+    /// each system
+    #[clap(long, conflicts_with = "per-station")]
+    pub factions: bool,
+}
+
+/// The systems to leave out, each with its own `--exclude-...` flag
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Exclusions(BTreeSet<Exclusion>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Exclusion {
+    PermitLocked,
+    RareCommodity,
+    PlayerFaction,
+    PlayerControlled,
+}
+
+impl Exclusion {
+    const ALL: [Exclusion; 4] = [
+        Exclusion::PermitLocked,
+        Exclusion::RareCommodity,
+        Exclusion::PlayerFaction,
+        Exclusion::PlayerControlled,
+    ];
+
+    const fn flag(self) -> &'static str {
+        match self {
+            Exclusion::PermitLocked => "exclude-permit-locked",
+            Exclusion::RareCommodity => "exclude-rare-commodity",
+            Exclusion::PlayerFaction => "exclude-player-faction",
+            Exclusion::PlayerControlled => "exclude-player-controlled",
+        }
+    }
+
+    const fn help(self) -> &'static str {
+        match self {
+            Exclusion::PermitLocked => "Exclude permit locked systems",
+            Exclusion::RareCommodity => "Exclude systems that sell rare commodities",
+            Exclusion::PlayerFaction => "Exclude systems that contain a player faction",
+            Exclusion::PlayerControlled => {
+                "Exclude systems controlled by a player faction, even if other player factions are \
+                 present"
+            }
+        }
+    }
+
+    fn filter<'a>(self) -> domain::SystemFilter<'a> {
+        match self {
+            Exclusion::PermitLocked => exclude_permit_locked(),
+            Exclusion::RareCommodity => exclude_rare_commodity(),
+            Exclusion::PlayerFaction => exclude_player_faction(),
+            Exclusion::PlayerControlled => exclude_player_controlled(),
+        }
+    }
+}
+
+impl FromIterator<Exclusion> for Exclusions {
+    fn from_iter<I: IntoIterator<Item = Exclusion>>(exclusions: I) -> Self {
+        Exclusions(exclusions.into_iter().collect())
+    }
+}
+
+impl FromArgMatches for Exclusions {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        Ok(Exclusion::ALL
+            .iter()
+            .copied()
+            .filter(|exclusion| matches.contains_id(exclusion.flag()))
+            .collect())
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+        Ok(())
+    }
+}
+
+impl Args for Exclusions {
+    fn augment_args(cmd: clap::Command<'_>) -> clap::Command<'_> {
+        Exclusion::ALL.iter().fold(cmd, |cmd, exclusion| {
+            cmd.arg(
+                Arg::new(exclusion.flag())
+                    .long(exclusion.flag())
+                    .help(exclusion.help()),
+            )
+        })
+    }
+
+    fn augment_args_for_update(cmd: clap::Command<'_>) -> clap::Command<'_> {
+        Self::augment_args(cmd)
+    }
+}
+
+/// Parses any value into a type with an `Unknown` variant, so values the game adds later can still
+/// be given, while listing the known ones in the help
+#[derive(Clone)]
+struct KnownValues<T> {
+    names: Vec<&'static str>,
+    value: PhantomData<T>,
+}
+
+impl<T> KnownValues<T> {
+    fn new(names: impl Iterator<Item = &'static str>) -> Self {
+        KnownValues {
+            names: names.collect(),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<T: for<'a> From<&'a str> + Clone + Send + Sync + 'static> TypedValueParser for KnownValues<T> {
+    type Value = T;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command<'_>,
+        arg: Option<&Arg<'_>>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        StringValueParser::new()
+            .parse_ref(cmd, arg, value)
+            .map(|value| T::from(&value))
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue<'static>> + '_>> {
+        Some(Box::new(self.names.iter().copied().map(PossibleValue::new)))
+    }
+}
+
+fn coords_from_str(value: &str) -> Result<Coords, String> {
+    let coordinates = value
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| error.to_string())?;
+
+    match coordinates.as_slice() {
+        [x, y, z] => Ok(Coords {
+            x: *x,
+            y: *y,
+            z: *z,
+        }),
+        _ => Err(String::from("expected three numbers separated by commas")),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Within {
+    name: String,
+    radius: f64,
+}
+
+impl FromStr for Within {
+    type Err = String;
+
+    fn from_str(within: &str) -> Result<Self, Self::Err> {
+        let (name, radius) = within
+            .rsplit_once(':')
+            .ok_or_else(|| String::from("expected a system name and radius, like Sol:15"))?;
+        let radius = radius
+            .parse()
+            .map_err(|error: ParseFloatError| error.to_string())?;
+
+        Ok(Within {
+            name: name.to_string(),
+            radius,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FactionInfluence {
+    name: String,
+    percent: f64,
+}
+
+impl FromStr for FactionInfluence {
+    type Err = String;
+
+    fn from_str(influence: &str) -> Result<Self, Self::Err> {
+        let (name, percent) = influence.rsplit_once(':').ok_or_else(|| {
+            String::from("expected a faction name and percentage, like Mother Gaia:40")
+        })?;
+        let percent = percent
+            .parse()
+            .map_err(|error: ParseFloatError| error.to_string())?;
+
+        Ok(FactionInfluence {
+            name: name.to_string(),
+            percent,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StationCount {
+    criteria: StationCriteria,
+    count: usize,
+}
+
+impl FromStr for StationCount {
+    type Err = String;
+
+    fn from_str(station_count: &str) -> Result<Self, Self::Err> {
+        let (criteria, count) = station_count.rsplit_once(':').ok_or_else(|| {
+            String::from("expected criteria and a count, like pad=large,orbital=true:3")
+        })?;
+        let count = count
+            .parse()
+            .map_err(|error: ParseIntError| error.to_string())?;
+
+        Ok(StationCount {
+            criteria: station_criteria(criteria)?,
+            count,
+        })
+    }
+}
+
+/// Criteria like `pad=large,orbital=true`, anything left out matches any station
+fn station_criteria(criteria: &str) -> Result<StationCriteria, String> {
+    let mut parsed = StationCriteria::default();
+    for criterion in criteria
+        .split(',')
+        .filter(|criterion| !criterion.is_empty())
+    {
+        let flag = |value: &str| {
+            value
+                .parse::<bool>()
+                .map_err(|_| format!("expected true or false, not {value}"))
+        };
+        match criterion.split_once('=') {
+            Some(("pad", "small")) => parsed.pad = Some(PadSize::Small),
+            Some(("pad", "medium")) => parsed.pad = Some(PadSize::Medium),
+            Some(("pad", "large")) => parsed.pad = Some(PadSize::Large),
+            Some(("pad", value)) => {
+                return Err(format!("expected small, medium or large, not {value}"))
+            }
+            Some(("orbital", value)) => parsed.orbital = Some(flag(value)?),
+            Some(("permanent", value)) => parsed.permanent = Some(flag(value)?),
+            _ => {
+                return Err(format!(
+                    "unknown criterion {criterion}, expected pad, orbital or permanent"
+                ))
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceCount {
+    service: String,
+    count: usize,
+}
+
+impl FromStr for ServiceCount {
+    type Err = String;
+
+    fn from_str(service_count: &str) -> Result<Self, Self::Err> {
+        let (service, count) = service_count
+            .rsplit_once(':')
+            .ok_or_else(|| String::from("expected a service and count, like Shipyard:2"))?;
+        let count = count
+            .parse()
+            .map_err(|error: ParseIntError| error.to_string())?;
+
+        Ok(ServiceCount {
+            service: service.to_string(),
+            count,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FactionState {
+    state: String,
+    stage: StateStage,
+}
+
+impl FromStr for FactionState {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (state_name, stage) = value.split_once(':').unwrap_or((value, "active"));
+        let stage = if stage.eq_ignore_ascii_case("pending") {
+            StateStage::Pending
+        } else if stage.eq_ignore_ascii_case("active") {
+            StateStage::Active
+        } else if stage.eq_ignore_ascii_case("recovering") {
+            StateStage::Recovering
+        } else {
+            return Err(format!(
+                "unknown state stage {stage}, expected pending, active or recovering"
+            ));
+        };
+
+        Ok(FactionState {
+            state: state_name.to_string(),
+            stage,
+        })
+    }
+}
+
+/// An inclusive range where either end can be left off, a single value matches only itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountRange<T> {
+    min: Option<T>,
+    max: Option<T>,
+}
+
+impl<T: FromStr + PartialOrd> FromStr for CountRange<T>
+where
+    T::Err: Display,
+{
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let (min, max) = range.split_once("..").unwrap_or((range, range));
+        let parse = |end: &str| {
+            (!end.is_empty())
+                .then(|| end.parse().map_err(|error: T::Err| error.to_string()))
+                .transpose()
+        };
+        let range = CountRange {
+            min: parse(min)?,
+            max: parse(max)?,
+        };
+
+        match (&range.min, &range.max) {
+            (Some(min), Some(max)) if min > max => Err(String::from(
+                "expected the start of the range to be no more than the end",
+            )),
+            (None, None) => Err(String::from(
+                "expected a range, like 1000..50000, 1000.. or ..50000",
+            )),
+            _ => Ok(range),
+        }
+    }
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopulationBracket {
+    /// Fewer than 100 thousand
+    Tiny,
+    /// 100 thousand up to 10 million
+    Small,
+    /// 10 million up to 1 billion
+    Medium,
+    /// 1 billion up to 10 billion
+    Large,
+    /// 10 billion or more
+    Huge,
+}
+
+impl PopulationBracket {
+    const fn range(self) -> CountRange<u128> {
+        let (min, max) = match self {
+            PopulationBracket::Tiny => (None, Some(99_999)),
+            PopulationBracket::Small => (Some(100_000), Some(9_999_999)),
+            PopulationBracket::Medium => (Some(10_000_000), Some(999_999_999)),
+            PopulationBracket::Large => (Some(1_000_000_000), Some(9_999_999_999)),
+            PopulationBracket::Huge => (Some(10_000_000_000), None),
+        };
+
+        CountRange { min, max }
+    }
+}
+
+#[derive(ArgEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WithinMode {
+    #[default]
+    All,
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKeyField {
+    Name,
+    DistanceFromReference,
+    DistanceFromSol,
+    Population,
+    LargeDocks,
+    Factions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    field: SortKeyField,
+    order: SortOrder,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let (field, order) = key.split_once(':').unwrap_or((key, "asc"));
+
+        let field = match field {
+            "name" => SortKeyField::Name,
+            "distance-from-reference" => SortKeyField::DistanceFromReference,
+            "distance-from-sol" => SortKeyField::DistanceFromSol,
+            "population" => SortKeyField::Population,
+            "large-docks" => SortKeyField::LargeDocks,
+            "factions" => SortKeyField::Factions,
+            _ => return Err(format!("unknown sort key {field}, expected one of name, distance-from-reference, distance-from-sol, population, large-docks or factions")),
+        };
+        let order = match order {
+            "asc" => SortOrder::Ascending,
+            "desc" => SortOrder::Descending,
+            _ => return Err(format!("unknown sort order {order}, expected asc or desc")),
+        };
+
+        Ok(SortKey { field, order })
+    }
+}
+
+/// Find the coordinates of the systems named by the reference and within switches, looking up
+/// anything that isn't a landmark in the systems
+pub fn locate_from_matches<'a, T, I>(
+    matches: &Cli,
+    systems: I,
+) -> Result<HashMap<String, Coords>, Error>
+where
+    T: System<'a>,
+    I: IntoIterator<Item = T>,
+{
+    let names = matches
         .reference
-        .as_ref()
-        .map(|reference_name| {
-            systems
-                .iter()
-                .find(|system| system.name() == reference_name)
-                .map(domain::System::coordinates)
-                .ok_or_else(|| Error::SystemNotFound(reference_name.into()))
+        .iter()
+        .chain(matches.within.iter().map(|within| &within.name))
+        .collect::<Vec<_>>();
+    let mut located = names
+        .iter()
+        .filter_map(|name| domain::landmark(name).map(|coords| ((*name).clone(), coords)))
+        .collect::<HashMap<_, _>>();
+    let mut remaining = names
+        .iter()
+        .map(|name| name.as_str())
+        .filter(|name| !located.contains_key(*name))
+        .collect::<HashSet<_>>();
+
+    if !remaining.is_empty() {
+        for system in systems {
+            if remaining.remove(system.name()) {
+                located.insert(system.name().to_string(), system.coordinates());
+                if remaining.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+
+    names
+        .into_iter()
+        .find(|name| !located.contains_key(*name))
+        .map_or(Ok(located), |name| Err(Error::SystemNotFound(name.clone())))
+}
+
+pub fn reference_from_matches(matches: &Cli, located: &HashMap<String, Coords>) -> Option<Coords> {
+    matches.reference_coords.or_else(|| {
+        matches
+            .reference
+            .as_ref()
+            .and_then(|reference_name| located.get(reference_name).copied())
+    })
+}
+
+pub fn within_from_matches<'a>(
+    matches: &Cli,
+    located: &HashMap<String, Coords>,
+) -> Option<domain::SystemFilter<'a>> {
+    let filters = matches
+        .within
+        .iter()
+        .filter_map(|within| {
+            located
+                .get(&within.name)
+                .map(|coords| max_distance_from_reference(*coords, within.radius))
+        })
+        .collect::<Vec<_>>();
+
+    match (filters.len(), matches.within_mode) {
+        (0, _) => None,
+        (1, _) => filters.into_iter().next(),
+        (_, WithinMode::All) => Some(and(filters)),
+        (_, WithinMode::Any) => Some(or(filters)),
+    }
+}
+
+pub fn station_parameters_from_matches(matches: &Cli) -> Vec<StationFilter<'_>> {
+    vec![
+        matches.station.map(station_matching),
+        matches
+            .per_station_max_distance_to_arrival
+            .map(max_distance_to_arrival),
+    ]
+    .into_iter()
+    .flatten()
+    .chain(
+        matches
+            .station_service
+            .iter()
+            .map(|service| station_with_service(service)),
+    )
+    .collect()
+}
+
+pub fn sort_from_matches(matches: &Cli, reference: Option<Coords>) -> Result<Vec<Sort>, Error> {
+    matches
+        .sort_by
+        .iter()
+        .map(|key| {
+            let field = match key.field {
+                SortKeyField::Name => SortField::Name,
+                SortKeyField::DistanceFromReference => SortField::DistanceFrom(
+                    reference.ok_or(Error::ReferenceRequired("distance-from-reference"))?,
+                ),
+                SortKeyField::DistanceFromSol => SortField::DistanceFrom(Coords {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                }),
+                SortKeyField::Population => SortField::Population,
+                SortKeyField::LargeDocks => SortField::LargeDocks,
+                SortKeyField::Factions => SortField::Factions,
+            };
+
+            Ok(sort_by(field, key.order))
         })
-        .map_or(Ok(None), |v| v.map(Some))?;
-
-    return Ok(vec![
-        matches.allegiance.as_ref().map(|x| allegiance(x)),
-        matches.government.as_ref().map(|x| government(x)),
-        matches.min_docks_large.map(min_large_docks),
-        matches.min_docks.map(min_docks),
-        matches.min_starports.map(min_starports),
+        .collect()
+}
+
+pub fn nearest_from_matches(
+    matches: &Cli,
+    reference: Option<Coords>,
+) -> Result<Option<(Coords, usize)>, Error> {
+    matches
+        .nearest
+        .map(|count| {
+            reference
+                .map(|reference| (reference, count))
+                .ok_or(Error::ReferenceRequired("nearest"))
+        })
+        .transpose()
+}
+
+pub fn parameters_from_matches(
+    matches: &Cli,
+    reference: Option<Coords>,
+) -> Result<Vec<domain::SystemFilter<'_>>, Error> {
+    let query = matches
+        .query
+        .as_ref()
+        .map(|query| query::parse(query, reference))
+        .transpose()?;
+
+    Ok(system_filters(matches)
+        .chain(dock_filters(matches))
+        .chain(distance_filters(matches, reference))
+        .chain(population_filters(matches))
+        .chain(faction_filters(matches))
+        .chain(matches.exclude.0.iter().map(|exclusion| exclusion.filter()))
+        .chain(query)
+        .collect())
+}
+
+fn system_filters(matches: &Cli) -> impl Iterator<Item = domain::SystemFilter<'_>> {
+    vec![
+        (!matches.allegiance.is_empty())
+            .then(|| allegiance_one_of(matches.allegiance.iter().cloned().collect())),
+        (!matches.not_allegiance.is_empty())
+            .then(|| exclude_allegiances(matches.not_allegiance.iter().cloned().collect())),
+        (!matches.government.is_empty())
+            .then(|| government_one_of(matches.government.iter().cloned().collect())),
+        (!matches.not_government.is_empty())
+            .then(|| exclude_governments(matches.not_government.iter().cloned().collect())),
+        matches.economy.clone().map(economy),
+        matches.security.clone().map(security),
+        matches.system_state.as_ref().map(|x| system_state(x)),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+fn dock_filters(matches: &Cli) -> impl Iterator<Item = domain::SystemFilter<'_>> {
+    vec![
+        matches.min_docks_large.map(|count| {
+            matches.dock_max_distance_to_arrival.map_or_else(
+                || min_large_docks(count),
+                |distance| min_large_docks_within(count, distance),
+            )
+        }),
+        matches.min_docks.map(|count| {
+            matches.dock_max_distance_to_arrival.map_or_else(
+                || min_docks(count),
+                |distance| min_docks_within(count, distance),
+            )
+        }),
+        matches.min_starports.map(|count| {
+            matches.dock_max_distance_to_arrival.map_or_else(
+                || min_starports(count),
+                |distance| min_starports_within(count, distance),
+            )
+        }),
+        matches.min_fleet_carriers.map(min_fleet_carriers),
+        matches.max_fleet_carriers.map(max_fleet_carriers),
+    ]
+    .into_iter()
+    .flatten()
+    .chain(matches.min_stations.iter().map(move |x| {
+        matches.dock_max_distance_to_arrival.map_or_else(
+            || min_stations(x.criteria, x.count),
+            |distance| min_stations_within(x.criteria, x.count, distance),
+        )
+    }))
+    .chain(matches.require_service.iter().map(|x| require_service(x)))
+    .chain(
+        matches
+            .min_stations_with_service
+            .iter()
+            .map(|x| min_stations_with_service(&x.service, x.count)),
+    )
+}
+
+fn distance_filters(
+    matches: &Cli,
+    reference: Option<Coords>,
+) -> impl Iterator<Item = domain::SystemFilter<'_>> {
+    vec![
         matches.max_distance_from_sol.map(max_distance_from_sol),
-        matches.min_population.map(min_population),
+        matches.min_distance_from_sol.map(min_distance_from_sol),
         matches
             .max_distance_from_reference
             .zip(reference)
             .map(|(distance, reference)| max_distance_from_reference(reference, distance)),
+        matches
+            .min_distance_from_reference
+            .zip(reference)
+            .map(|(distance, reference)| min_distance_from_reference(reference, distance)),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+fn population_filters(matches: &Cli) -> impl Iterator<Item = domain::SystemFilter<'_>> {
+    vec![
+        matches.min_population.map(min_population),
+        matches.max_population.map(max_population),
+        matches
+            .population
+            .and_then(|range| range.min)
+            .map(min_population),
+        matches
+            .population
+            .and_then(|range| range.max)
+            .map(max_population),
+        matches
+            .population_bracket
+            .and_then(|bracket| bracket.range().min)
+            .map(min_population),
+        matches
+            .population_bracket
+            .and_then(|bracket| bracket.range().max)
+            .map(max_population),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+fn faction_filters(matches: &Cli) -> impl Iterator<Item = domain::SystemFilter<'_>> {
+    vec![
         matches.max_number_of_factions.map(max_number_of_factions),
-        if matches.exclude_permit_locked {
-            Some(exclude_permit_locked())
-        } else {
-            None
-        },
-        if matches.exclude_rare_commodity {
-            Some(exclude_rare_commodity())
-        } else {
-            None
-        },
-        if matches.exclude_player_faction {
-            Some(exclude_player_faction())
-        } else {
-            None
-        },
+        matches.min_number_of_factions.map(min_number_of_factions),
+        matches
+            .number_of_factions
+            .and_then(|range| range.min)
+            .map(min_number_of_factions),
+        matches
+            .number_of_factions
+            .and_then(|range| range.max)
+            .map(max_number_of_factions),
+        matches
+            .controlling_faction
+            .as_ref()
+            .map(|x| controlled_by(x)),
+        matches
+            .controlling_allegiance
+            .clone()
+            .map(controlling_faction_allegiance),
+        matches
+            .controlling_government
+            .clone()
+            .map(controlling_faction_government),
+        matches
+            .max_controlling_influence
+            .map(|percent| max_controlling_faction_influence(percent / 100.0)),
+        matches
+            .max_controlling_lead
+            .map(|percent| max_controlling_faction_lead(percent / 100.0)),
     ]
     .into_iter()
     .flatten()
-    .collect());
+    .chain(matches.faction_present.iter().map(|x| faction_present(x)))
+    .chain(matches.faction_absent.iter().map(|x| faction_absent(x)))
+    .chain(
+        matches
+            .min_faction_influence
+            .iter()
+            .map(|x| min_faction_influence(&x.name, x.percent / 100.0)),
+    )
+    .chain(
+        matches
+            .faction_state
+            .iter()
+            .map(|x| faction_in_state(&x.state, x.stage)),
+    )
 }
 
 #[derive(ThisError, Debug)]
@@ -120,207 +981,954 @@ pub enum Error {
     InvalidFloat(#[from] ParseFloatError),
     #[error("system not found: {0}")]
     SystemNotFound(String),
+    #[error("{0} needs a reference system")]
+    ReferenceRequired(&'static str),
+    #[error("invalid query")]
+    InvalidQuery(#[from] query::Error),
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::cli::{parameters_from_matches, Cli};
-    use crate::domain::{
-        allegiance, government, max_distance_from_reference, max_distance_from_sol, min_docks,
-        min_large_docks, min_population, min_starports, Coords,
-    };
-    use crate::{domain, stub};
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::cli::{
+        coords_from_str, locate_from_matches, nearest_from_matches, parameters_from_matches,
+        reference_from_matches, sort_from_matches, station_parameters_from_matches,
+        within_from_matches, Cli, CountRange, Exclusion, FactionInfluence, FactionState,
+        PopulationBracket, ServiceCount, SortKey, StationCount, Within, WithinMode,
+    };
+    use crate::domain::{
+        allegiance_one_of, exclude_allegiances, exclude_governments, government_one_of,
+        max_distance_from_reference, max_distance_from_sol, max_distance_to_arrival,
+        max_fleet_carriers, max_number_of_factions, max_population, min_docks, min_docks_within,
+        min_fleet_carriers, min_large_docks, min_large_docks_within, min_number_of_factions,
+        min_population, min_starports, min_starports_within, sort_by, station_matching,
+        station_with_service, Allegiance, Coords, Economy, Government, PadSize, Security,
+        SortField, SortOrder, StateStage, StationCriteria,
+    };
+    use crate::output::Format;
+    use crate::{domain, stub};
+
+    #[test]
+    fn no_switches() {
+        let args = Cli::default();
+        assert_eq!(parameters_from_matches(&args, None).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn large_docks_present() {
+        let args = Cli {
+            min_docks_large: Some(10),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_large_docks(10)]
+        );
+    }
+
+    #[test]
+    fn docks_within_station_distance_present() {
+        let args = Cli {
+            min_docks_large: Some(1),
+            min_docks: Some(2),
+            min_starports: Some(3),
+            dock_max_distance_to_arrival: Some(1000.0),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                min_large_docks_within(1, 1000.0),
+                min_docks_within(2, 1000.0),
+                min_starports_within(3, 1000.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn min_population_present() {
+        let args = Cli {
+            min_population: Some(10),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_population(10)]
+        );
+    }
+
+    #[test]
+    fn docks_present() {
+        let args = Cli {
+            min_docks: Some(10),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_docks(10)]
+        );
+    }
+
+    #[test]
+    fn starports_present() {
+        let args = Cli {
+            min_starports: Some(10),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_starports(10)]
+        );
+    }
+
+    #[test]
+    fn population_range() {
+        let args = Cli {
+            max_population: Some(50_000),
+            population: Some("1000..".parse().unwrap()),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![max_population(50_000), min_population(1000)]
+        );
+
+        let args = Cli {
+            population: Some("1000".parse().unwrap()),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_population(1000), max_population(1000)]
+        );
+    }
+
+    #[test]
+    fn number_of_factions() {
+        let args = Cli {
+            min_number_of_factions: Some(2),
+            number_of_factions: Some("..6".parse().unwrap()),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_number_of_factions(2), max_number_of_factions(6)]
+        );
+
+        let args = Cli {
+            number_of_factions: Some("1".parse().unwrap()),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_number_of_factions(1), max_number_of_factions(1)]
+        );
+    }
+
+    #[test]
+    fn fleet_carriers() {
+        let args = Cli {
+            min_fleet_carriers: Some(1),
+            max_fleet_carriers: Some(5),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_fleet_carriers(1), max_fleet_carriers(5)]
+        );
+    }
+
+    #[test]
+    fn population_bracket() {
+        let args = Cli {
+            population_bracket: Some(PopulationBracket::Small),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_population(100_000), max_population(9_999_999)]
+        );
+
+        let args = Cli {
+            population_bracket: Some(PopulationBracket::Tiny),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![max_population(99_999)]
+        );
+    }
+
+    #[test]
+    fn invalid_count_ranges() {
+        assert!("..".parse::<CountRange<u128>>().is_err());
+        assert!("10..1".parse::<CountRange<u128>>().is_err());
+        assert!("lots..".parse::<CountRange<u128>>().is_err());
+        assert!("-1..".parse::<CountRange<u128>>().is_err());
+    }
+
+    #[test]
+    fn population_present() {
+        let args = Cli {
+            min_population: Some(25_000_000_000),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![min_population(25_000_000_000)]
+        );
+    }
+
+    #[test]
+    fn allegiance_matches() {
+        let args = Cli {
+            allegiance: vec![Allegiance::Alliance],
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![allegiance_one_of(
+                vec![Allegiance::Alliance].into_iter().collect()
+            )]
+        );
+    }
+
+    #[test]
+    fn government_matches() {
+        let args = Cli {
+            government: vec![Government::Democracy],
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![government_one_of(
+                vec![Government::Democracy].into_iter().collect()
+            )]
+        );
+    }
+
+    #[test]
+    fn several_allegiances_and_governments() {
+        let args = Cli::try_parse_from([
+            "ed-system-search",
+            "--government",
+            "Democracy,Confederacy,Cooperative",
+            "--not-allegiance",
+            "Empire",
+            "--not-allegiance",
+            "Alliance",
+            "--not-government",
+            "Anarchy,Dictatorship",
+            "dump.json.gz",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                exclude_allegiances(
+                    vec![Allegiance::Empire, Allegiance::Alliance]
+                        .into_iter()
+                        .collect()
+                ),
+                government_one_of(
+                    vec![
+                        Government::Democracy,
+                        Government::Confederacy,
+                        Government::Cooperative
+                    ]
+                    .into_iter()
+                    .collect()
+                ),
+                exclude_governments(
+                    vec![Government::Anarchy, Government::Dictatorship]
+                        .into_iter()
+                        .collect()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn allegiance_and_government_ignore_case() {
+        let args = Cli::try_parse_from([
+            "ed-system-search",
+            "--allegiance",
+            "federation",
+            "--controlling-government",
+            "PRISON COLONY",
+            "dump.json.gz",
+        ])
+        .unwrap();
+
+        assert_eq!(args.allegiance, vec![Allegiance::Federation]);
+        assert_eq!(args.controlling_government, Some(Government::PrisonColony));
+    }
+
+    #[test]
+    fn station_parameters() {
+        let args = Cli::try_parse_from([
+            "ed-system-search",
+            "--per-station",
+            "--station",
+            "pad=large,orbital=false",
+            "--station-service",
+            "Shipyard,Material Trader",
+            "--per-station-max-distance-to-arrival",
+            "1000",
+            "dump.json.gz",
+        ])
+        .unwrap();
+
+        assert!(args.per_station);
+        assert_eq!(
+            station_parameters_from_matches(&args),
+            vec![
+                station_matching(StationCriteria {
+                    pad: Some(PadSize::Large),
+                    orbital: Some(false),
+                    permanent: None,
+                }),
+                max_distance_to_arrival(1000.0),
+                station_with_service("Shipyard"),
+                station_with_service("Material Trader"),
+            ]
+        );
+    }
+
+    #[test]
+    fn station_parameters_need_per_station() {
+        assert!(Cli::try_parse_from([
+            "ed-system-search",
+            "--station-service",
+            "Shipyard",
+            "dump.json.gz",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn factions() {
+        let args = Cli::try_parse_from([
+            "ed-system-search",
+            "--allegiance",
+            "Federation",
+            "--factions",
+            "--output",
+            "table",
+            "dump.json.gz",
+        ])
+        .unwrap();
+
+        assert!(args.factions);
+        assert_eq!(args.output, Format::Table);
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![allegiance_one_of(
+                vec![Allegiance::Federation].into_iter().collect()
+            )]
+        );
+    }
+
+    #[test]
+    fn factions_conflicts_with_per_station() {
+        assert!(Cli::try_parse_from([
+            "ed-system-search",
+            "--per-station",
+            "--factions",
+            "dump.json.gz",
+        ])
+        .is_err());
+    }
 
     #[test]
-    fn no_switches() {
-        let args = Cli::default();
+    fn values_the_game_adds_later_are_kept() {
+        let args = Cli::try_parse_from([
+            "ed-system-search",
+            "--government",
+            "Colony",
+            "--allegiance",
+            "federation",
+            "dump.json.gz",
+        ])
+        .unwrap();
+
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![]
+            args.government,
+            vec![Government::Unknown("Colony".to_string())]
         );
+        assert_eq!(args.allegiance, vec![Allegiance::Federation]);
     }
 
     #[test]
-    fn large_docks_present() {
+    fn economy_security_and_state_match() {
         let args = Cli {
-            min_docks_large: Some(10),
+            economy: Some(Economy::Industrial),
+            security: Some(Security::High),
+            system_state: Some("Boom".to_string()),
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![min_large_docks(10)]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                domain::economy(Economy::Industrial),
+                domain::security(Security::High),
+                domain::system_state("Boom"),
+            ]
         );
     }
 
     #[test]
-    fn min_population_present() {
+    fn exclude_permit_locked() {
+        let args = Cli::try_parse_from([
+            "ed-system-search",
+            "--exclude-permit-locked",
+            "dump.json.gz",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![domain::exclude_permit_locked()]
+        );
+    }
+
+    #[test]
+    fn exclude_rare_commodity() {
+        let args = Cli::try_parse_from([
+            "ed-system-search",
+            "--exclude-rare-commodity",
+            "dump.json.gz",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![domain::exclude_rare_commodity()]
+        );
+    }
+
+    #[test]
+    fn exclude_player_faction() {
+        let args = Cli::try_parse_from([
+            "ed-system-search",
+            "--exclude-player-faction",
+            "dump.json.gz",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![domain::exclude_player_faction()]
+        );
+    }
+
+    #[test]
+    fn max_factions_present() {
         let args = Cli {
-            min_population: Some(10),
+            max_number_of_factions: Some(10),
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![min_population(10)]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![domain::max_number_of_factions(10)]
         );
     }
 
     #[test]
-    fn docks_present() {
+    fn distance_from_sol_present() {
         let args = Cli {
-            min_docks: Some(10),
+            max_distance_from_sol: Some(10.),
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![min_docks(10)]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![max_distance_from_sol(10.0)]
         );
     }
 
     #[test]
-    fn starports_present() {
+    fn distance_range_from_reference_present() {
         let args = Cli {
-            min_starports: Some(10),
+            min_distance_from_sol: Some(100.),
+            min_distance_from_reference: Some(20.),
+            max_distance_from_reference: Some(40.),
+            reference: Some("Sol".to_string()),
             ..Cli::default()
         };
+        let reference = Coords {
+            x: f64::from(1),
+            y: f64::from(2),
+            z: f64::from(3),
+        };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![min_starports(10)]
+            parameters_from_matches(&args, Some(reference)).unwrap(),
+            vec![
+                domain::min_distance_from_sol(100.0),
+                max_distance_from_reference(reference, 40.0),
+                domain::min_distance_from_reference(reference, 20.0),
+            ]
         );
     }
 
     #[test]
-    fn population_present() {
+    fn factions_present_and_absent() {
         let args = Cli {
-            min_population: Some(25_000_000_000),
+            faction_present: vec!["Mother Gaia".to_string(), "Sol Workers' Party".to_string()],
+            faction_absent: vec!["Federal Liberal Command".to_string()],
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![min_population(25_000_000_000)]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                domain::faction_present("Mother Gaia"),
+                domain::faction_present("Sol Workers' Party"),
+                domain::faction_absent("Federal Liberal Command"),
+            ]
         );
     }
 
     #[test]
-    fn allegiance_matches() {
+    fn influence_present() {
         let args = Cli {
-            allegiance: Some("Alliance".to_string()),
+            max_controlling_influence: Some(50.0),
+            max_controlling_lead: Some(10.0),
+            min_faction_influence: vec!["Mother Gaia:12.5".parse().unwrap()],
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![allegiance("Alliance")]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                domain::max_controlling_faction_influence(0.5),
+                domain::max_controlling_faction_lead(0.1),
+                domain::min_faction_influence("Mother Gaia", 0.125),
+            ]
         );
     }
 
     #[test]
-    fn government_matches() {
+    fn min_stations_present() {
+        let args = Cli {
+            min_stations: vec![
+                "pad=large,orbital=true,permanent=true:3".parse().unwrap(),
+                "permanent=false:1".parse().unwrap(),
+            ],
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                min_large_docks(3),
+                domain::min_stations(
+                    StationCriteria {
+                        permanent: Some(false),
+                        ..StationCriteria::default()
+                    },
+                    1
+                )
+            ]
+        );
+
         let args = Cli {
-            government: Some("Democracy".to_string()),
+            min_stations: vec!["pad=medium:2".parse().unwrap()],
+            dock_max_distance_to_arrival: Some(1000.0),
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![government("Democracy")]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![domain::min_stations_within(
+                StationCriteria {
+                    pad: Some(PadSize::Medium),
+                    ..StationCriteria::default()
+                },
+                2,
+                1000.0
+            )]
         );
     }
 
     #[test]
-    fn exclude_permit_locked() {
+    fn invalid_min_stations() {
+        assert!("pad=large".parse::<StationCount>().is_err());
+        assert!("pad=huge:1".parse::<StationCount>().is_err());
+        assert!("orbital=yes:1".parse::<StationCount>().is_err());
+        assert!("size=large:1".parse::<StationCount>().is_err());
+        assert!("pad=large:lots".parse::<StationCount>().is_err());
+    }
+
+    #[test]
+    fn services_present() {
         let args = Cli {
-            exclude_permit_locked: true,
+            require_service: vec![
+                String::from("Material Trader"),
+                String::from("Interstellar Factors Contact"),
+            ],
+            min_stations_with_service: vec!["Shipyard:2".parse().unwrap()],
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![domain::exclude_permit_locked()]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                domain::require_service("Material Trader"),
+                domain::require_service("Interstellar Factors Contact"),
+                domain::min_stations_with_service("Shipyard", 2),
+            ]
         );
     }
 
     #[test]
-    fn exclude_rare_commodity() {
+    fn invalid_service_count() {
+        assert!("Shipyard".parse::<ServiceCount>().is_err());
+        assert!("Shipyard:lots".parse::<ServiceCount>().is_err());
+    }
+
+    #[test]
+    fn invalid_faction_influence() {
+        assert!("Mother Gaia".parse::<FactionInfluence>().is_err());
+        assert!("Mother Gaia:lots".parse::<FactionInfluence>().is_err());
+    }
+
+    #[test]
+    fn faction_states_present() {
         let args = Cli {
-            exclude_rare_commodity: true,
+            faction_state: vec![
+                "War".parse().unwrap(),
+                "Expansion:pending".parse().unwrap(),
+                "Infrastructure Failure:recovering".parse().unwrap(),
+            ],
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![domain::exclude_rare_commodity()]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                domain::faction_in_state("War", StateStage::Active),
+                domain::faction_in_state("Expansion", StateStage::Pending),
+                domain::faction_in_state("Infrastructure Failure", StateStage::Recovering),
+            ]
         );
+        assert!("War:over".parse::<FactionState>().is_err());
     }
 
     #[test]
-    fn exclude_player_faction() {
+    fn faction_states_ignore_case() {
         let args = Cli {
-            exclude_player_faction: true,
+            faction_state: vec!["war".parse().unwrap(), "expansion:Pending".parse().unwrap()],
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![domain::exclude_player_faction()]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                domain::faction_in_state("war", StateStage::Active),
+                domain::faction_in_state("expansion", StateStage::Pending),
+            ]
         );
     }
 
     #[test]
-    fn max_factions_present() {
+    fn controlling_faction_present() {
         let args = Cli {
-            max_number_of_factions: Some(10),
+            exclude: vec![Exclusion::PlayerControlled].into_iter().collect(),
+            controlling_faction: Some("Mother Gaia".to_string()),
+            controlling_allegiance: Some(Allegiance::Federation),
+            controlling_government: Some(Government::Democracy),
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![domain::max_number_of_factions(10)]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![
+                domain::controlled_by("Mother Gaia"),
+                domain::controlling_faction_allegiance(Allegiance::Federation),
+                domain::controlling_faction_government(Government::Democracy),
+                domain::exclude_player_controlled(),
+            ]
         );
     }
 
     #[test]
-    fn distance_from_sol_present() {
+    fn query_present() {
         let args = Cli {
-            max_distance_from_sol: Some(10.),
+            query: Some(r#"allegiance = "Alliance" or government = "Democracy""#.to_string()),
             ..Cli::default()
         };
 
         assert_eq!(
-            parameters_from_matches(&args, &[] as &[stub::System]).unwrap(),
-            vec![max_distance_from_sol(10.0)]
+            parameters_from_matches(&args, None).unwrap(),
+            vec![domain::or(vec![
+                domain::allegiance(Allegiance::Alliance),
+                domain::government(Government::Democracy)
+            ])]
         );
     }
 
     #[test]
-    fn reference_system_not_found() {
+    fn invalid_query() {
         let args = Cli {
-            max_distance_from_reference: Some(10.),
-            reference: Some("Missing".to_string()),
+            query: Some("allegiance =".to_string()),
             ..Cli::default()
         };
 
-        assert!(parameters_from_matches(
-            &args,
-            &[stub::System {
-                name: "Sol".into(),
-                coords: Coords {
+        assert!(parameters_from_matches(&args, None).is_err());
+    }
+
+    #[test]
+    fn query_uses_reference() {
+        let args = Cli {
+            query: Some("distance_from_reference <= 10".to_string()),
+            reference: Some("Sol".to_string()),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            parameters_from_matches(
+                &args,
+                Some(Coords {
+                    x: f64::from(0),
+                    y: f64::from(0),
+                    z: f64::from(0),
+                }),
+            )
+            .unwrap(),
+            vec![max_distance_from_reference(
+                Coords {
                     x: f64::from(0),
                     y: f64::from(0),
                     z: f64::from(0),
                 },
+                10.0,
+            )]
+        );
+    }
+
+    fn dump() -> [stub::System; 2] {
+        [
+            stub::System {
+                name: "Sol".into(),
+                coords: Coords {
+                    x: f64::from(1),
+                    y: f64::from(2),
+                    z: f64::from(3),
+                },
                 stations: vec![],
                 population: 0,
                 factions: vec![],
                 allegiance: String::new(),
-                government: String::new()
-            }],
-        )
-        .is_err(),);
+                government: String::new(),
+                controlling_faction: None,
+                id64: None,
+                economy: String::new(),
+                security: String::new(),
+                state: String::new(),
+            },
+            stub::System {
+                name: "Sanos".into(),
+                coords: Coords {
+                    x: 73.875,
+                    y: -3.5625,
+                    z: -52.625,
+                },
+                stations: vec![],
+                population: 0,
+                factions: vec![],
+                allegiance: String::new(),
+                government: String::new(),
+                controlling_faction: None,
+                id64: None,
+                economy: String::new(),
+                security: String::new(),
+                state: String::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn reference_system_not_found() {
+        let args = Cli {
+            max_distance_from_reference: Some(10.),
+            reference: Some("Missing".to_string()),
+            ..Cli::default()
+        };
+
+        assert!(locate_from_matches(&args, dump()).is_err());
+    }
+
+    #[test]
+    fn reference_system_found() {
+        let args = Cli {
+            max_distance_from_reference: Some(10.),
+            reference: Some("Sol".to_string()),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            reference_from_matches(&args, &locate_from_matches(&args, dump()).unwrap()),
+            Some(Coords {
+                x: f64::from(1),
+                y: f64::from(2),
+                z: f64::from(3),
+            })
+        );
+    }
+
+    #[test]
+    fn reference_landmark() {
+        let args = Cli {
+            reference: Some("Colonia".to_string()),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            reference_from_matches(
+                &args,
+                &locate_from_matches(&args, [] as [stub::System; 0]).unwrap()
+            ),
+            Some(Coords {
+                x: -9_530.5,
+                y: -910.281_25,
+                z: 19_808.125,
+            })
+        );
+    }
+
+    #[test]
+    fn reference_coordinates() {
+        let args = Cli {
+            reference_coords: Some(coords_from_str("25.21875, -20.90625,25899.96875").unwrap()),
+            ..Cli::default()
+        };
+
+        assert_eq!(
+            reference_from_matches(
+                &args,
+                &locate_from_matches(&args, [] as [stub::System; 0]).unwrap()
+            ),
+            Some(Coords {
+                x: 25.218_75,
+                y: -20.906_25,
+                z: 25_899.968_75,
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_reference_coordinates() {
+        assert!(coords_from_str("1,2").is_err());
+        assert!(coords_from_str("1,2,3,4").is_err());
+        assert!(coords_from_str("1,two,3").is_err());
+    }
+
+    #[test]
+    fn no_reference() {
+        assert_eq!(
+            reference_from_matches(
+                &Cli::default(),
+                &locate_from_matches(&Cli::default(), [] as [stub::System; 0]).unwrap()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn within_all() {
+        let args = Cli {
+            within: vec!["Sol:15".parse().unwrap(), "Sanos:40.5".parse().unwrap()],
+            ..Cli::default()
+        };
+        let located = locate_from_matches(&args, dump()).unwrap();
+
+        assert_eq!(
+            within_from_matches(&args, &located),
+            Some(domain::and(vec![
+                max_distance_from_reference(located["Sol"], 15.0),
+                max_distance_from_reference(located["Sanos"], 40.5),
+            ]))
+        );
+    }
+
+    #[test]
+    fn within_any() {
+        let args = Cli {
+            within: vec![
+                "Sol:15".parse().unwrap(),
+                "Beagle Point:40".parse().unwrap(),
+            ],
+            within_mode: WithinMode::Any,
+            ..Cli::default()
+        };
+        let located = locate_from_matches(&args, dump()).unwrap();
+
+        assert_eq!(
+            within_from_matches(&args, &located),
+            Some(domain::or(vec![
+                max_distance_from_reference(located["Sol"], 15.0),
+                max_distance_from_reference(domain::landmark("Beagle Point").unwrap(), 40.0),
+            ]))
+        );
+    }
+
+    #[test]
+    fn within_one_system() {
+        let args = Cli {
+            within: vec!["Sol:15".parse().unwrap()],
+            ..Cli::default()
+        };
+        let located = locate_from_matches(&args, dump()).unwrap();
+
+        assert_eq!(
+            within_from_matches(&args, &located),
+            Some(max_distance_from_reference(located["Sol"], 15.0))
+        );
+        assert_eq!(within_from_matches(&Cli::default(), &located), None);
+    }
+
+    #[test]
+    fn within_system_not_found() {
+        let args = Cli {
+            within: vec!["Sol:15".parse().unwrap(), "Missing:10".parse().unwrap()],
+            ..Cli::default()
+        };
+
+        assert!(locate_from_matches(&args, dump()).is_err());
+    }
+
+    #[test]
+    fn invalid_within() {
+        assert!("Sol".parse::<Within>().is_err());
+        assert!("Sol:far".parse::<Within>().is_err());
+        assert_eq!(
+            "LHS 1:2:3".parse::<Within>().unwrap(),
+            Within {
+                name: "LHS 1:2".to_string(),
+                radius: 3.0
+            }
+        );
     }
 
     #[test]
@@ -334,19 +1942,11 @@ mod tests {
         assert_eq!(
             parameters_from_matches(
                 &args,
-                &[stub::System {
-                    name: "Sol".into(),
-                    coords: Coords {
-                        x: f64::from(0),
-                        y: f64::from(0),
-                        z: f64::from(0),
-                    },
-                    stations: vec![],
-                    population: 0,
-                    factions: vec![],
-                    allegiance: String::new(),
-                    government: String::new(),
-                }],
+                Some(Coords {
+                    x: f64::from(0),
+                    y: f64::from(0),
+                    z: f64::from(0),
+                }),
             )
             .unwrap(),
             vec![max_distance_from_reference(
@@ -359,4 +1959,69 @@ mod tests {
             )]
         );
     }
+
+    #[test]
+    fn sort_keys_present() {
+        let args = Cli {
+            sort_by: vec![
+                "distance-from-reference".parse().unwrap(),
+                "population:desc".parse().unwrap(),
+                "name:asc".parse().unwrap(),
+            ],
+            ..Cli::default()
+        };
+        let reference = Coords {
+            x: f64::from(1),
+            y: f64::from(2),
+            z: f64::from(3),
+        };
+
+        assert_eq!(
+            sort_from_matches(&args, Some(reference)).unwrap(),
+            vec![
+                sort_by(SortField::DistanceFrom(reference), SortOrder::Ascending),
+                sort_by(SortField::Population, SortOrder::Descending),
+                sort_by(SortField::Name, SortOrder::Ascending),
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_by_distance_from_reference_needs_a_reference() {
+        let args = Cli {
+            sort_by: vec!["distance-from-reference".parse().unwrap()],
+            ..Cli::default()
+        };
+
+        assert!(sort_from_matches(&args, None).is_err());
+    }
+
+    #[test]
+    fn invalid_sort_keys() {
+        assert!("distance".parse::<SortKey>().is_err());
+        assert!("population:up".parse::<SortKey>().is_err());
+    }
+
+    #[test]
+    fn nearest_present() {
+        let args = Cli {
+            nearest: Some(10),
+            ..Cli::default()
+        };
+        let reference = Coords {
+            x: f64::from(1),
+            y: f64::from(2),
+            z: f64::from(3),
+        };
+
+        assert_eq!(
+            nearest_from_matches(&args, Some(reference)).unwrap(),
+            Some((reference, 10))
+        );
+        assert!(nearest_from_matches(&args, None).is_err());
+        assert_eq!(
+            nearest_from_matches(&Cli::default(), Some(reference)).unwrap(),
+            None
+        );
+    }
 }
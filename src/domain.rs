@@ -7,108 +7,689 @@ pub struct Coords {
     pub(crate) z: f64,
 }
 
+/// Well known places that aren't in the populated systems dump
+const LANDMARKS: [(&str, Coords); 3] = [
+    (
+        "Sagittarius A*",
+        Coords {
+            x: 25.218_75,
+            y: -20.906_25,
+            z: 25_899.968_75,
+        },
+    ),
+    (
+        "Colonia",
+        Coords {
+            x: -9_530.5,
+            y: -910.281_25,
+            z: 19_808.125,
+        },
+    ),
+    (
+        "Beagle Point",
+        Coords {
+            x: -1_111.562_5,
+            y: -134.218_75,
+            z: 65_269.75,
+        },
+    ),
+];
+
+pub fn landmark(name: &str) -> Option<Coords> {
+    LANDMARKS
+        .iter()
+        .find(|(landmark, _)| landmark.eq_ignore_ascii_case(name))
+        .map(|(_, coords)| *coords)
+}
+
 pub trait System<'system> {
     fn name(&self) -> &str;
     fn allegiance(&self) -> &str;
     fn government(&self) -> &str;
     fn stations(&self) -> Vec<Box<dyn Station>>;
     fn factions(&self) -> Vec<Box<dyn Faction>>;
+    fn controlling_faction(&self) -> Option<&dyn ControllingFaction>;
     fn coordinates(&self) -> Coords;
     fn population(&self) -> u128;
+    fn id64(&self) -> Option<u64>;
+    fn economy(&self) -> &str;
+    fn security(&self) -> &str;
+    fn state(&self) -> &str;
 }
 
 pub trait Station {
+    fn name(&self) -> &str;
     fn station_type(&self) -> Option<&str>;
+    fn distance_to_arrival(&self) -> Option<f64>;
+    fn allegiance(&self) -> &str;
+    fn government(&self) -> &str;
+    fn economy(&self) -> &str;
+    fn has_market(&self) -> bool;
+    fn has_shipyard(&self) -> bool;
+    fn has_outfitting(&self) -> bool;
+    /// Everything offered besides the market, shipyard and outfitting, like "Material Trader"
+    fn other_services(&self) -> Vec<&str>;
+}
+
+/// Whether a station offers a service, where the market, shipyard and outfitting count as
+/// services too
+pub fn has_service(station: &dyn Station, service: &str) -> bool {
+    if service.eq_ignore_ascii_case("Market") {
+        station.has_market()
+    } else if service.eq_ignore_ascii_case("Shipyard") {
+        station.has_shipyard()
+    } else if service.eq_ignore_ascii_case("Outfitting") {
+        station.has_outfitting()
+    } else {
+        station
+            .other_services()
+            .iter()
+            .any(|other| other.eq_ignore_ascii_case(service))
+    }
+}
+
+/// Every service a station offers, starting with the market, shipyard and outfitting
+pub fn services(station: &dyn Station) -> Vec<&str> {
+    vec![
+        (station.has_market(), "Market"),
+        (station.has_shipyard(), "Shipyard"),
+        (station.has_outfitting(), "Outfitting"),
+    ]
+    .into_iter()
+    .filter_map(|(offered, service)| offered.then_some(service))
+    .chain(station.other_services())
+    .collect()
+}
+
+pub trait ControllingFaction {
+    fn name(&self) -> &str;
+    fn allegiance(&self) -> &str;
+    fn government(&self) -> &str;
+    fn is_player(&self) -> bool;
 }
 
 pub trait Faction {
+    fn name(&self) -> &str;
+    fn allegiance(&self) -> &str;
+    fn government(&self) -> &str;
+    /// The share of the system's influence, between 0 and 1
+    fn influence(&self) -> f64;
+    fn state(&self) -> &str;
+    fn active_states(&self) -> Vec<&str>;
+    fn pending_states(&self) -> Vec<&str>;
+    fn recovering_states(&self) -> Vec<&str>;
+    fn happiness(&self) -> &str;
     fn is_player(&self) -> bool;
 }
 
+/// How far through a minor faction is with a state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateStage {
+    Pending,
+    Active,
+    Recovering,
+}
+
+/// Find a value by the name the game uses for it, ignoring case
+fn known_value<T: Clone>(known: &[(T, &str)], name: &str) -> Option<T> {
+    known
+        .iter()
+        .find(|(_, known_name)| known_name.eq_ignore_ascii_case(name))
+        .map(|(value, _)| value.clone())
+}
+
+fn known_names<'a, T>(known: &'static [(T, &'static str)]) -> impl Iterator<Item = &'a str> {
+    known.iter().map(|(_, name)| -> &'a str { name })
+}
+
+/// A superpower, anything the game adds later is kept as `Unknown`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Allegiance {
+    Alliance,
+    Empire,
+    Federation,
+    Independent,
+    PilotsFederation,
+    Thargoid,
+    Guardian,
+    None,
+    Unknown(String),
+}
+
+const ALLEGIANCES: [(Allegiance, &str); 8] = [
+    (Allegiance::Alliance, "Alliance"),
+    (Allegiance::Empire, "Empire"),
+    (Allegiance::Federation, "Federation"),
+    (Allegiance::Independent, "Independent"),
+    (Allegiance::PilotsFederation, "Pilots Federation"),
+    (Allegiance::Thargoid, "Thargoid"),
+    (Allegiance::Guardian, "Guardian"),
+    (Allegiance::None, "None"),
+];
+
+impl Allegiance {
+    pub fn names<'a>() -> impl Iterator<Item = &'a str> {
+        known_names(&ALLEGIANCES)
+    }
+}
+
+impl From<&str> for Allegiance {
+    fn from(name: &str) -> Self {
+        known_value(&ALLEGIANCES, name).unwrap_or_else(|| Self::Unknown(name.to_string()))
+    }
+}
+
+/// A government type, anything the game adds later is kept as `Unknown`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Government {
+    Anarchy,
+    Communism,
+    Confederacy,
+    Cooperative,
+    Corporate,
+    Democracy,
+    Dictatorship,
+    Feudal,
+    Patronage,
+    PrisonColony,
+    Theocracy,
+    Engineer,
+    FleetCarrier,
+    Prison,
+    None,
+    Unknown(String),
+}
+
+const GOVERNMENTS: [(Government, &str); 15] = [
+    (Government::Anarchy, "Anarchy"),
+    (Government::Communism, "Communism"),
+    (Government::Confederacy, "Confederacy"),
+    (Government::Cooperative, "Cooperative"),
+    (Government::Corporate, "Corporate"),
+    (Government::Democracy, "Democracy"),
+    (Government::Dictatorship, "Dictatorship"),
+    (Government::Feudal, "Feudal"),
+    (Government::Patronage, "Patronage"),
+    (Government::PrisonColony, "Prison colony"),
+    (Government::Theocracy, "Theocracy"),
+    (Government::Engineer, "Engineer"),
+    (Government::FleetCarrier, "Fleet Carrier"),
+    (Government::Prison, "Prison"),
+    (Government::None, "None"),
+];
+
+impl Government {
+    pub fn names<'a>() -> impl Iterator<Item = &'a str> {
+        known_names(&GOVERNMENTS)
+    }
+}
+
+impl From<&str> for Government {
+    fn from(name: &str) -> Self {
+        known_value(&GOVERNMENTS, name).unwrap_or_else(|| Self::Unknown(name.to_string()))
+    }
+}
+
+/// A system's economy, anything the game adds later is kept as `Unknown`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Economy {
+    Agriculture,
+    Colony,
+    Damaged,
+    Extraction,
+    HighTech,
+    Industrial,
+    Military,
+    Prison,
+    Refinery,
+    Repair,
+    Rescue,
+    Service,
+    Terraforming,
+    Tourism,
+    None,
+    Unknown(String),
+}
+
+const ECONOMIES: [(Economy, &str); 15] = [
+    (Economy::Agriculture, "Agriculture"),
+    (Economy::Colony, "Colony"),
+    (Economy::Damaged, "Damaged"),
+    (Economy::Extraction, "Extraction"),
+    (Economy::HighTech, "High Tech"),
+    (Economy::Industrial, "Industrial"),
+    (Economy::Military, "Military"),
+    (Economy::Prison, "Prison"),
+    (Economy::Refinery, "Refinery"),
+    (Economy::Repair, "Repair"),
+    (Economy::Rescue, "Rescue"),
+    (Economy::Service, "Service"),
+    (Economy::Terraforming, "Terraforming"),
+    (Economy::Tourism, "Tourism"),
+    (Economy::None, "None"),
+];
+
+impl Economy {
+    pub fn names<'a>() -> impl Iterator<Item = &'a str> {
+        known_names(&ECONOMIES)
+    }
+}
+
+impl From<&str> for Economy {
+    fn from(name: &str) -> Self {
+        known_value(&ECONOMIES, name).unwrap_or_else(|| Self::Unknown(name.to_string()))
+    }
+}
+
+/// A system's security level, anything the game adds later is kept as `Unknown`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Security {
+    High,
+    Medium,
+    Low,
+    Anarchy,
+    Unknown(String),
+}
+
+const SECURITIES: [(Security, &str); 4] = [
+    (Security::High, "High"),
+    (Security::Medium, "Medium"),
+    (Security::Low, "Low"),
+    (Security::Anarchy, "Anarchy"),
+];
+
+impl Security {
+    pub fn names<'a>() -> impl Iterator<Item = &'a str> {
+        known_names(&SECURITIES)
+    }
+}
+
+impl From<&str> for Security {
+    fn from(name: &str) -> Self {
+        known_value(&SECURITIES, name).unwrap_or_else(|| Self::Unknown(name.to_string()))
+    }
+}
+
+/// The kind of station, anything the game adds later is kept as `Unknown`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum StationType {
+    AsteroidBase,
+    CoriolisStarport,
+    OcellusStarport,
+    OrbisStarport,
+    Outpost,
+    PlanetaryOutpost,
+    PlanetaryPort,
+    MegaShip,
+    FleetCarrier,
+    OdysseySettlement,
+    Unknown(String),
+}
+
+const STATION_TYPES: [(StationType, &str); 10] = [
+    (StationType::AsteroidBase, "Asteroid base"),
+    (StationType::CoriolisStarport, "Coriolis Starport"),
+    (StationType::OcellusStarport, "Ocellus Starport"),
+    (StationType::OrbisStarport, "Orbis Starport"),
+    (StationType::Outpost, "Outpost"),
+    (StationType::PlanetaryOutpost, "Planetary Outpost"),
+    (StationType::PlanetaryPort, "Planetary Port"),
+    (StationType::MegaShip, "Mega ship"),
+    (StationType::FleetCarrier, "Fleet Carrier"),
+    (StationType::OdysseySettlement, "Odyssey Settlement"),
+];
+
+impl From<&str> for StationType {
+    fn from(name: &str) -> Self {
+        known_value(&STATION_TYPES, name).unwrap_or_else(|| Self::Unknown(name.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PadSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl PadSize {
+    pub const fn name(self) -> &'static str {
+        match self {
+            PadSize::Small => "small",
+            PadSize::Medium => "medium",
+            PadSize::Large => "large",
+        }
+    }
+}
+
+/// What ships can expect when they arrive at a type of station
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StationDetails {
+    /// The largest landing pad that every station of this type has
+    pub(crate) pad: PadSize,
+    /// In space, rather than on a planet's surface
+    pub(crate) orbital: bool,
+    /// Stays in the system, unlike carriers and mega ships that move on
+    pub(crate) permanent: bool,
+}
+
+impl StationType {
+    /// Every station type we know about is listed here, the match makes sure a type added to the
+    /// enum can't be forgotten
+    pub const fn details(&self) -> Option<StationDetails> {
+        let (pad, orbital, permanent) = match self {
+            StationType::AsteroidBase
+            | StationType::CoriolisStarport
+            | StationType::OcellusStarport
+            | StationType::OrbisStarport => (PadSize::Large, true, true),
+            StationType::Outpost => (PadSize::Medium, true, true),
+            StationType::PlanetaryOutpost | StationType::PlanetaryPort => {
+                (PadSize::Large, false, true)
+            }
+            StationType::MegaShip | StationType::FleetCarrier => (PadSize::Large, true, false),
+            StationType::OdysseySettlement => (PadSize::Small, false, true),
+            StationType::Unknown(_) => return None,
+        };
+
+        Some(StationDetails {
+            pad,
+            orbital,
+            permanent,
+        })
+    }
+}
+
+/// Which station types to count, anything left as `None` doesn't matter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StationCriteria {
+    /// The smallest pad size needed, larger pads also match
+    pub(crate) pad: Option<PadSize>,
+    pub(crate) orbital: Option<bool>,
+    pub(crate) permanent: Option<bool>,
+}
+
+impl StationCriteria {
+    pub(crate) fn matches(self, details: StationDetails) -> bool {
+        self.pad.is_none_or(|pad| details.pad >= pad)
+            && self
+                .orbital
+                .is_none_or(|orbital| details.orbital == orbital)
+            && self
+                .permanent
+                .is_none_or(|permanent| details.permanent == permanent)
+    }
+}
+
+/// The known station types meeting the criteria, types we don't know about never match
+pub fn station_types(criteria: StationCriteria) -> HashSet<StationType> {
+    STATION_TYPES
+        .iter()
+        .map(|(station_type, _)| station_type)
+        .filter(|station_type| {
+            station_type
+                .details()
+                .is_some_and(|details| criteria.matches(details))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Checks on a single station, for when it's the stations we're after rather than the systems
+#[derive(Debug, PartialEq, Clone)]
+pub enum StationFilter<'a> {
+    Criteria(StationCriteria),
+    Service(&'a str),
+    MaximumDistanceToArrival(f64),
+}
+
+pub const fn station_matching<'a>(criteria: StationCriteria) -> StationFilter<'a> {
+    StationFilter::Criteria(criteria)
+}
+
+pub const fn station_with_service(service: &str) -> StationFilter<'_> {
+    StationFilter::Service(service)
+}
+
+pub const fn max_distance_to_arrival<'a>(light_seconds: f64) -> StationFilter<'a> {
+    StationFilter::MaximumDistanceToArrival(light_seconds)
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum SystemFilter<'a> {
-    Allegiance(&'a str),
-    Government(&'a str),
+    Allegiance(Allegiance),
+    Government(Government),
+    AllegianceOneOf(HashSet<Allegiance>),
+    GovernmentOneOf(HashSet<Government>),
+    ExcludeAllegiances(HashSet<Allegiance>),
+    ExcludeGovernments(HashSet<Government>),
+    Economy(Economy),
+    Security(Security),
+    SystemState(&'a str),
     MaximumDistanceFrom(Coords, f64),
-    MinimumStationCount(HashSet<&'a str>, usize),
+    MinimumDistanceFrom(Coords, f64),
+    MinimumStationCount(HashSet<StationType>, usize),
+    MinimumStationCountWithin(HashSet<StationType>, usize, f64),
+    MaximumStationCount(HashSet<StationType>, usize),
+    MinimumStationsWithService(&'a str, usize),
     MaximumFactionCount(usize),
+    MinimumFactionCount(usize),
     MinimumPopulation(u128),
+    MaximumPopulation(u128),
     ExcludeSystems(HashSet<&'a str>),
     ExcludeSystemsWithPlayerFactions,
+    FactionPresent(&'a str),
+    FactionAbsent(&'a str),
+    MaximumControllingFactionInfluence(f64),
+    MaximumControllingFactionLead(f64),
+    MinimumFactionInfluence(&'a str, f64),
+    FactionInState(&'a str, StateStage),
+    ControlledBy(&'a str),
+    ControllingFactionAllegiance(Allegiance),
+    ControllingFactionGovernment(Government),
+    ExcludeSystemsWithPlayerControllingFaction,
+    And(Vec<SystemFilter<'a>>),
+    Or(Vec<SystemFilter<'a>>),
+    Not(Box<SystemFilter<'a>>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortField {
+    Name,
+    DistanceFrom(Coords),
+    Population,
+    LargeDocks,
+    Factions,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
 }
 
-pub const fn allegiance(allegiance: &str) -> SystemFilter<'_> {
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sort {
+    pub(crate) field: SortField,
+    pub(crate) order: SortOrder,
+}
+
+pub const fn sort_by(field: SortField, order: SortOrder) -> Sort {
+    Sort { field, order }
+}
+
+pub const fn allegiance<'a>(allegiance: Allegiance) -> SystemFilter<'a> {
     SystemFilter::Allegiance(allegiance)
 }
 
-pub const fn government(government: &str) -> SystemFilter<'_> {
+pub const fn government<'a>(government: Government) -> SystemFilter<'a> {
     SystemFilter::Government(government)
 }
 
+pub const fn allegiance_one_of<'a>(allegiances: HashSet<Allegiance>) -> SystemFilter<'a> {
+    SystemFilter::AllegianceOneOf(allegiances)
+}
+
+pub const fn government_one_of<'a>(governments: HashSet<Government>) -> SystemFilter<'a> {
+    SystemFilter::GovernmentOneOf(governments)
+}
+
+pub const fn exclude_allegiances<'a>(allegiances: HashSet<Allegiance>) -> SystemFilter<'a> {
+    SystemFilter::ExcludeAllegiances(allegiances)
+}
+
+pub const fn exclude_governments<'a>(governments: HashSet<Government>) -> SystemFilter<'a> {
+    SystemFilter::ExcludeGovernments(governments)
+}
+
+pub fn large_dock_types() -> HashSet<StationType> {
+    station_types(StationCriteria {
+        pad: Some(PadSize::Large),
+        orbital: Some(true),
+        permanent: Some(true),
+    })
+}
+
+pub fn dock_types() -> HashSet<StationType> {
+    station_types(StationCriteria {
+        pad: Some(PadSize::Medium),
+        orbital: None,
+        permanent: Some(true),
+    })
+}
+
+/// Large orbital docks other than asteroid bases
+pub fn starport_types() -> HashSet<StationType> {
+    let mut starports = large_dock_types();
+    starports.remove(&StationType::AsteroidBase);
+    starports
+}
+
+/// Player owned carriers, which come and go and so never count as docks
+pub fn fleet_carrier_types() -> HashSet<StationType> {
+    vec![StationType::FleetCarrier].into_iter().collect()
+}
+
+pub fn count_stations<'a, T: System<'a>>(system: &T, types: &HashSet<StationType>) -> usize {
+    count_station_types(&system.stations(), types)
+}
+
+pub fn count_station_types(stations: &[Box<dyn Station>], types: &HashSet<StationType>) -> usize {
+    stations
+        .iter()
+        .filter(|station| {
+            station
+                .station_type()
+                .is_some_and(|station_type| types.contains(&StationType::from(station_type)))
+        })
+        .count()
+}
+
+pub fn min_fleet_carriers<'a>(count: usize) -> SystemFilter<'a> {
+    SystemFilter::MinimumStationCount(fleet_carrier_types(), count)
+}
+
+pub fn max_fleet_carriers<'a>(count: usize) -> SystemFilter<'a> {
+    SystemFilter::MaximumStationCount(fleet_carrier_types(), count)
+}
+
+pub fn min_stations<'a>(criteria: StationCriteria, count: usize) -> SystemFilter<'a> {
+    SystemFilter::MinimumStationCount(station_types(criteria), count)
+}
+
+pub fn min_stations_within<'a>(
+    criteria: StationCriteria,
+    count: usize,
+    light_seconds: f64,
+) -> SystemFilter<'a> {
+    SystemFilter::MinimumStationCountWithin(station_types(criteria), count, light_seconds)
+}
+
 pub fn min_large_docks<'a>(count: usize) -> SystemFilter<'a> {
-    SystemFilter::MinimumStationCount(
-        vec![
-            "Asteroid base",
-            "Coriolis Starport",
-            "Ocellus Starport",
-            "Orbis Starport",
-        ]
-        .into_iter()
-        .collect(),
-        count,
-    )
+    SystemFilter::MinimumStationCount(large_dock_types(), count)
+}
+
+pub fn min_large_docks_within<'a>(count: usize, light_seconds: f64) -> SystemFilter<'a> {
+    SystemFilter::MinimumStationCountWithin(large_dock_types(), count, light_seconds)
+}
+
+pub const fn require_service(service: &str) -> SystemFilter<'_> {
+    SystemFilter::MinimumStationsWithService(service, 1)
+}
+
+pub const fn min_stations_with_service(service: &str, count: usize) -> SystemFilter<'_> {
+    SystemFilter::MinimumStationsWithService(service, count)
 }
 
 pub fn min_docks<'a>(count: usize) -> SystemFilter<'a> {
-    SystemFilter::MinimumStationCount(
-        vec![
-            ("Asteroid base"),
-            ("Coriolis Starport"),
-            ("Ocellus Starport"),
-            ("Orbis Starport"),
-            ("Outpost"),
-            ("Planetary Outpost"),
-            ("Planetary Port"),
-        ]
-        .into_iter()
-        .collect(),
-        count,
-    )
+    SystemFilter::MinimumStationCount(dock_types(), count)
+}
+
+pub fn min_docks_within<'a>(count: usize, light_seconds: f64) -> SystemFilter<'a> {
+    SystemFilter::MinimumStationCountWithin(dock_types(), count, light_seconds)
 }
 
-pub const fn max_distance_from_sol<'a>(light_seconds: f64) -> SystemFilter<'a> {
+pub const fn economy<'a>(economy: Economy) -> SystemFilter<'a> {
+    SystemFilter::Economy(economy)
+}
+
+pub const fn security<'a>(security: Security) -> SystemFilter<'a> {
+    SystemFilter::Security(security)
+}
+
+pub const fn system_state(state: &str) -> SystemFilter<'_> {
+    SystemFilter::SystemState(state)
+}
+
+pub const fn max_distance_from_sol<'a>(light_years: f64) -> SystemFilter<'a> {
     SystemFilter::MaximumDistanceFrom(
         Coords {
             x: 0.0,
             y: 0.0,
             z: 0.0,
         },
-        light_seconds,
+        light_years,
     )
 }
 
 pub const fn max_distance_from_reference<'a>(
     reference: Coords,
-    light_seconds: f64,
+    light_years: f64,
+) -> SystemFilter<'a> {
+    SystemFilter::MaximumDistanceFrom(reference, light_years)
+}
+
+pub const fn min_distance_from_sol<'a>(light_years: f64) -> SystemFilter<'a> {
+    SystemFilter::MinimumDistanceFrom(
+        Coords {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        light_years,
+    )
+}
+
+pub const fn min_distance_from_reference<'a>(
+    reference: Coords,
+    light_years: f64,
 ) -> SystemFilter<'a> {
-    SystemFilter::MaximumDistanceFrom(reference, light_seconds)
+    SystemFilter::MinimumDistanceFrom(reference, light_years)
 }
 
 pub const fn min_population<'a>(count: u128) -> SystemFilter<'a> {
     SystemFilter::MinimumPopulation(count)
 }
 
+pub const fn max_population<'a>(count: u128) -> SystemFilter<'a> {
+    SystemFilter::MaximumPopulation(count)
+}
+
 pub fn min_starports<'a>(count: usize) -> SystemFilter<'a> {
-    SystemFilter::MinimumStationCount(
-        vec![
-            ("Coriolis Starport"),
-            ("Ocellus Starport"),
-            ("Orbis Starport"),
-        ]
-        .into_iter()
-        .collect(),
-        count,
-    )
+    SystemFilter::MinimumStationCount(starport_types(), count)
+}
+
+pub fn min_starports_within<'a>(count: usize, light_seconds: f64) -> SystemFilter<'a> {
+    SystemFilter::MinimumStationCountWithin(starport_types(), count, light_seconds)
 }
 
 pub fn exclude_permit_locked<'a>() -> SystemFilter<'a> {
@@ -325,6 +906,166 @@ pub const fn max_number_of_factions<'a>(count: usize) -> SystemFilter<'a> {
     SystemFilter::MaximumFactionCount(count)
 }
 
+pub const fn min_number_of_factions<'a>(count: usize) -> SystemFilter<'a> {
+    SystemFilter::MinimumFactionCount(count)
+}
+
 pub const fn exclude_player_faction<'a>() -> SystemFilter<'a> {
     SystemFilter::ExcludeSystemsWithPlayerFactions
 }
+
+pub const fn faction_present(name: &str) -> SystemFilter<'_> {
+    SystemFilter::FactionPresent(name)
+}
+
+pub const fn faction_absent(name: &str) -> SystemFilter<'_> {
+    SystemFilter::FactionAbsent(name)
+}
+
+pub const fn controlled_by(name: &str) -> SystemFilter<'_> {
+    SystemFilter::ControlledBy(name)
+}
+
+pub const fn controlling_faction_allegiance<'a>(allegiance: Allegiance) -> SystemFilter<'a> {
+    SystemFilter::ControllingFactionAllegiance(allegiance)
+}
+
+pub const fn controlling_faction_government<'a>(government: Government) -> SystemFilter<'a> {
+    SystemFilter::ControllingFactionGovernment(government)
+}
+
+pub const fn exclude_player_controlled<'a>() -> SystemFilter<'a> {
+    SystemFilter::ExcludeSystemsWithPlayerControllingFaction
+}
+
+pub const fn max_controlling_faction_influence<'a>(influence: f64) -> SystemFilter<'a> {
+    SystemFilter::MaximumControllingFactionInfluence(influence)
+}
+
+pub const fn max_controlling_faction_lead<'a>(influence: f64) -> SystemFilter<'a> {
+    SystemFilter::MaximumControllingFactionLead(influence)
+}
+
+pub const fn min_faction_influence(name: &str, influence: f64) -> SystemFilter<'_> {
+    SystemFilter::MinimumFactionInfluence(name, influence)
+}
+
+pub const fn faction_in_state(state_name: &str, stage: StateStage) -> SystemFilter<'_> {
+    SystemFilter::FactionInState(state_name, stage)
+}
+
+pub const fn and(filters: Vec<SystemFilter<'_>>) -> SystemFilter<'_> {
+    SystemFilter::And(filters)
+}
+
+pub const fn or(filters: Vec<SystemFilter<'_>>) -> SystemFilter<'_> {
+    SystemFilter::Or(filters)
+}
+
+pub fn not(filter: SystemFilter<'_>) -> SystemFilter<'_> {
+    match filter {
+        SystemFilter::Not(filter) => *filter,
+        filter => SystemFilter::Not(Box::new(filter)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::domain::{
+        dock_types, large_dock_types, services, starport_types, station_types, Allegiance,
+        Government, PadSize, StationCriteria, StationType,
+    };
+    use crate::stub;
+
+    fn types(station_types: &[&str]) -> HashSet<StationType> {
+        station_types
+            .iter()
+            .map(|station_type| StationType::from(*station_type))
+            .collect()
+    }
+
+    #[test]
+    fn names_ignore_case() {
+        assert_eq!(Allegiance::from("federation"), Allegiance::Federation);
+        assert_eq!(Government::from("PRISON COLONY"), Government::PrisonColony);
+        assert_eq!(
+            StationType::from("asteroid BASE"),
+            StationType::AsteroidBase
+        );
+    }
+
+    #[test]
+    fn names_the_game_added_later_are_kept() {
+        assert_eq!(
+            Government::from("Colony"),
+            Government::Unknown(String::from("Colony"))
+        );
+        assert_eq!(StationType::from("Dockable Space Bus").details(), None);
+    }
+
+    #[test]
+    fn docks() {
+        assert_eq!(
+            large_dock_types(),
+            types(&[
+                "Asteroid base",
+                "Coriolis Starport",
+                "Ocellus Starport",
+                "Orbis Starport"
+            ])
+        );
+        assert_eq!(
+            dock_types(),
+            types(&[
+                "Asteroid base",
+                "Coriolis Starport",
+                "Ocellus Starport",
+                "Orbis Starport",
+                "Outpost",
+                "Planetary Outpost",
+                "Planetary Port"
+            ])
+        );
+        assert_eq!(
+            starport_types(),
+            types(&["Coriolis Starport", "Ocellus Starport", "Orbis Starport"])
+        );
+    }
+
+    #[test]
+    fn station_types_by_criteria() {
+        assert_eq!(
+            station_types(StationCriteria {
+                permanent: Some(false),
+                ..StationCriteria::default()
+            }),
+            types(&["Fleet Carrier", "Mega ship"])
+        );
+        assert_eq!(
+            station_types(StationCriteria {
+                pad: Some(PadSize::Large),
+                orbital: Some(false),
+                ..StationCriteria::default()
+            }),
+            types(&["Planetary Outpost", "Planetary Port"])
+        );
+        assert_eq!(station_types(StationCriteria::default()).len(), 10);
+    }
+
+    #[test]
+    fn services_include_the_market_shipyard_and_outfitting() {
+        let station = stub::Station {
+            has_market: true,
+            has_outfitting: true,
+            other_services: vec![String::from("Material Trader")],
+            ..stub::Station::default()
+        };
+
+        assert_eq!(
+            services(&station),
+            vec!["Market", "Outfitting", "Material Trader"]
+        );
+    }
+}
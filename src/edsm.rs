@@ -1,4 +1,5 @@
-use std::io::Read;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
 
 use serde::Deserialize;
 use thiserror::Error as ThisError;
@@ -7,22 +8,55 @@ use crate::domain;
 use crate::domain::Coords as DomainCoords;
 
 #[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct FactionState {
+    pub(crate) state: String,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
 pub struct Faction {
+    pub(crate) name: String,
+    pub(crate) allegiance: Option<String>,
+    pub(crate) government: Option<String>,
+    pub(crate) influence: Option<f64>,
+    pub(crate) state: Option<String>,
+    #[serde(rename = "activeStates", default)]
+    pub(crate) active_states: Vec<FactionState>,
+    #[serde(rename = "pendingStates", default)]
+    pub(crate) pending_states: Vec<FactionState>,
+    #[serde(rename = "recoveringStates", default)]
+    pub(crate) recovering_states: Vec<FactionState>,
+    pub(crate) happiness: Option<String>,
     #[serde(rename = "isPlayer")]
     pub(crate) is_player: bool,
 }
 
 #[derive(Deserialize, Debug, PartialEq, Clone)]
 pub struct Station {
+    pub(crate) name: Option<String>,
     #[serde(rename = "type")]
-    pub(crate) station_type: Option<String>,
+    pub(crate) kind: Option<String>,
     #[serde(rename = "distanceToArrival")]
     pub(crate) distance_to_arrival: Option<f64>,
+    pub(crate) allegiance: Option<String>,
+    pub(crate) government: Option<String>,
+    pub(crate) economy: Option<String>,
+    #[serde(rename = "haveMarket", default)]
+    pub(crate) have_market: bool,
+    #[serde(rename = "haveShipyard", default)]
+    pub(crate) have_shipyard: bool,
+    #[serde(rename = "haveOutfitting", default)]
+    pub(crate) have_outfitting: bool,
+    #[serde(rename = "otherServices", default)]
+    pub(crate) other_services: Vec<String>,
 }
 
 #[derive(Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct ControllingFaction {
+    pub(crate) name: Option<String>,
     pub(crate) allegiance: Option<String>,
+    pub(crate) government: Option<String>,
+    #[serde(rename = "isPlayer", default)]
+    pub(crate) is_player: bool,
 }
 
 #[derive(Deserialize, Debug, PartialEq, Clone)]
@@ -43,6 +77,10 @@ pub struct System {
     pub(crate) stations: Option<Vec<Station>>,
     pub(crate) allegiance: Option<String>,
     pub(crate) government: Option<String>,
+    pub(crate) id64: Option<u64>,
+    pub(crate) economy: Option<String>,
+    pub(crate) security: Option<String>,
+    pub(crate) state: Option<String>,
 }
 
 impl<'system> domain::System<'system> for System {
@@ -82,6 +120,13 @@ impl<'system> domain::System<'system> for System {
             .unwrap_or_default()
     }
 
+    fn controlling_faction(&self) -> Option<&dyn domain::ControllingFaction> {
+        self.controlling_faction
+            .name
+            .is_some()
+            .then_some(&self.controlling_faction as &dyn domain::ControllingFaction)
+    }
+
     fn coordinates(&self) -> DomainCoords {
         DomainCoords {
             x: self.coords.x,
@@ -93,28 +138,244 @@ impl<'system> domain::System<'system> for System {
     fn population(&self) -> u128 {
         self.population.unwrap_or_default()
     }
+
+    fn id64(&self) -> Option<u64> {
+        self.id64
+    }
+
+    fn economy(&self) -> &str {
+        self.economy.as_ref().map_or("", |value| value)
+    }
+
+    fn security(&self) -> &str {
+        self.security.as_ref().map_or("", |value| value)
+    }
+
+    fn state(&self) -> &str {
+        self.state.as_ref().map_or("", |value| value)
+    }
 }
 
 impl domain::Station for Station {
+    fn name(&self) -> &str {
+        self.name.as_ref().map_or("", |value| value)
+    }
+
     fn station_type(&self) -> Option<&str> {
-        self.station_type.as_deref()
+        self.kind.as_deref()
+    }
+
+    fn distance_to_arrival(&self) -> Option<f64> {
+        self.distance_to_arrival
+    }
+
+    fn allegiance(&self) -> &str {
+        self.allegiance.as_ref().map_or("", |value| value)
+    }
+
+    fn government(&self) -> &str {
+        self.government.as_ref().map_or("", |value| value)
+    }
+
+    fn economy(&self) -> &str {
+        self.economy.as_ref().map_or("", |value| value)
+    }
+
+    fn has_market(&self) -> bool {
+        self.have_market
+    }
+
+    fn has_shipyard(&self) -> bool {
+        self.have_shipyard
+    }
+
+    fn has_outfitting(&self) -> bool {
+        self.have_outfitting
+    }
+
+    fn other_services(&self) -> Vec<&str> {
+        self.other_services.iter().map(String::as_str).collect()
+    }
+}
+
+impl domain::ControllingFaction for ControllingFaction {
+    fn name(&self) -> &str {
+        self.name.as_ref().map_or("", |value| value)
+    }
+
+    fn allegiance(&self) -> &str {
+        self.allegiance.as_ref().map_or("", |value| value)
+    }
+
+    fn government(&self) -> &str {
+        self.government.as_ref().map_or("", |value| value)
+    }
+
+    fn is_player(&self) -> bool {
+        self.is_player
     }
 }
 
 impl domain::Faction for Faction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn allegiance(&self) -> &str {
+        self.allegiance.as_ref().map_or("", |value| value)
+    }
+
+    fn government(&self) -> &str {
+        self.government.as_ref().map_or("", |value| value)
+    }
+
+    fn influence(&self) -> f64 {
+        self.influence.unwrap_or_default()
+    }
+
+    fn state(&self) -> &str {
+        self.state.as_ref().map_or("", |value| value)
+    }
+
+    fn active_states(&self) -> Vec<&str> {
+        state_names(&self.active_states)
+    }
+
+    fn pending_states(&self) -> Vec<&str> {
+        state_names(&self.pending_states)
+    }
+
+    fn recovering_states(&self) -> Vec<&str> {
+        state_names(&self.recovering_states)
+    }
+
+    fn happiness(&self) -> &str {
+        self.happiness.as_ref().map_or("", |value| value)
+    }
+
     fn is_player(&self) -> bool {
         self.is_player
     }
 }
 
-pub fn parse<R: Read>(file: R) -> Result<Vec<System>, Error> {
-    serde_json::from_reader::<_, _>(file).map_err(Error::Parse)
+fn state_names(states: &[FactionState]) -> Vec<&str> {
+    states.iter().map(|state| state.state.as_str()).collect()
+}
+
+pub fn parse<R: Read>(file: R) -> Systems<R> {
+    Systems {
+        reader: BufReader::new(file),
+        state: State::Start,
+    }
+}
+
+/// Stop at the first system that fails to parse, keeping the error to report once the systems
+/// have been consumed
+pub fn until_error<'e, R: Read + 'e>(
+    systems: Systems<R>,
+    error: &'e mut Option<Error>,
+) -> impl Iterator<Item = System> + 'e {
+    systems.scan(error, |error, system| {
+        system
+            .map_err(|parse_error| **error = Some(parse_error))
+            .ok()
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Start,
+    Element,
+    Separator,
+    Done,
+}
+
+/// Systems read one at a time from the top level array of the dump
+#[derive(Debug)]
+pub struct Systems<R> {
+    reader: BufReader<R>,
+    state: State,
+}
+
+impl<R: Read> Systems<R> {
+    fn peek(&mut self) -> Result<Option<u8>, Error> {
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+                Some(position) => {
+                    let byte = buffer[position];
+                    self.reader.consume(position);
+                    return Ok(Some(byte));
+                }
+                None if buffer.is_empty() => return Ok(None),
+                None => {
+                    let length = buffer.len();
+                    self.reader.consume(length);
+                }
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: &'static str, matches: &[u8]) -> Result<u8, Error> {
+        match self.peek()? {
+            Some(byte) if matches.contains(&byte) => {
+                self.reader.consume(1);
+                Ok(byte)
+            }
+            _ => Err(Error::Expected(expected)),
+        }
+    }
+
+    fn next_system(&mut self) -> Result<Option<System>, Error> {
+        loop {
+            match self.state {
+                State::Start => {
+                    self.expect("the start of an array", b"[")?;
+                    self.state = if self.peek()? == Some(b']') {
+                        self.reader.consume(1);
+                        State::Done
+                    } else {
+                        State::Element
+                    };
+                }
+                State::Separator => {
+                    self.state = match self.expect("',' or ']'", b",]")? {
+                        b',' => State::Element,
+                        _ => State::Done,
+                    };
+                }
+                State::Element => {
+                    let mut deserializer = serde_json::Deserializer::from_reader(&mut self.reader);
+                    let system = System::deserialize(&mut deserializer)?;
+                    self.state = State::Separator;
+                    return Ok(Some(system));
+                }
+                State::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Systems<R> {
+    type Item = Result<System, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let system = self.next_system();
+        if system.is_err() {
+            self.state = State::Done;
+        }
+        system.transpose()
+    }
 }
 
 #[derive(ThisError, Debug)]
 pub enum Error {
     #[error("failed to parse edsm data dump: {0:?}")]
     Parse(#[from] serde_json::Error),
+    #[error("failed to parse edsm data dump: expected {0}")]
+    Expected(&'static str),
+    #[error("failed to read edsm data dump: {0:?}")]
+    Read(#[from] io::Error),
 }
 
 #[cfg(test)]
@@ -123,11 +384,59 @@ mod tests {
     use indoc::indoc;
 
     use crate::domain::System as DomainSystem;
-    use crate::edsm::{parse, ControllingFaction, Coords, Faction, Station, System};
+    use crate::edsm::{parse, until_error, ControllingFaction, Coords, Faction, Station, System};
 
     #[tokio::test]
     async fn invalid_json() {
-        assert!(parse(b"This is not valid json" as &[u8]).is_err());
+        assert!(parse(b"This is not valid json" as &[u8])
+            .collect::<Result<Vec<_>, _>>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn truncated_json() {
+        assert!(parse(br#"[{"name":"Sol"}"# as &[u8])
+            .collect::<Result<Vec<_>, _>>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn empty_array() {
+        assert_eq!(parse(b" [ ] " as &[u8]).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn one_system_per_line() {
+        let example_json = indoc! {r#"
+            [
+            {"name":"Sol","coords":{"x":0,"y":0,"z":0},"controllingFaction":{}},
+            {"name":"Sanos","coords":{"x":73.875,"y":-3.5625,"z":-52.625},"controllingFaction":{}}
+            ]
+        "#};
+
+        assert_eq!(
+            parse(example_json.as_bytes())
+                .map(|system| system.unwrap().name)
+                .collect::<Vec<_>>(),
+            vec!["Sol", "Sanos"]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_after_error() {
+        let mut error = None;
+        let names = until_error(
+            parse(
+                br#"[{"name":"Sol","coords":{"x":0,"y":0,"z":0},"controllingFaction":{}},{"#
+                    as &[u8],
+            ),
+            &mut error,
+        )
+        .map(|system| system.name)
+        .collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["Sol"]);
+        assert!(error.is_some());
     }
 
     #[tokio::test]
@@ -137,7 +446,9 @@ mod tests {
         "#};
 
         assert_eq!(
-            parse(example_json.as_bytes()).unwrap(),
+            parse(example_json.as_bytes())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
             vec![System {
                 name: String::from("Sanos"),
                 coords: Coords {
@@ -147,24 +458,88 @@ mod tests {
                 },
                 population: Some(6_149_044_545),
                 controlling_faction: ControllingFaction {
-                    allegiance: Some("Federation".to_string())
+                    name: Some("Federal Liberal Command".to_string()),
+                    allegiance: Some("Federation".to_string()),
+                    government: Some("Corporate".to_string()),
+                    is_player: true
                 },
                 factions: Some(vec![
-                    Faction { is_player: true },
-                    Faction { is_player: false }
+                    Faction {
+                        name: String::from("Federal Liberal Command"),
+                        allegiance: Some(String::from("Federation")),
+                        government: Some(String::from("Corporate")),
+                        influence: Some(0.523_904),
+                        state: Some(String::from("None")),
+                        active_states: vec![],
+                        pending_states: vec![],
+                        recovering_states: vec![],
+                        happiness: Some(String::from("Happy")),
+                        is_player: true
+                    },
+                    Faction {
+                        name: String::from("Independents of Sanos"),
+                        allegiance: Some(String::from("Federation")),
+                        government: Some(String::from("Democracy")),
+                        influence: Some(0.167_331),
+                        state: Some(String::from("None")),
+                        active_states: vec![],
+                        pending_states: vec![],
+                        recovering_states: vec![],
+                        happiness: Some(String::from("Happy")),
+                        is_player: false
+                    }
                 ]),
                 stations: Some(vec![
                     Station {
-                        station_type: Some(String::from("Fleet Carrier")),
+                        name: Some(String::from("K9T-38W")),
+                        kind: Some(String::from("Fleet Carrier")),
                         distance_to_arrival: Some(296.807_847),
+                        allegiance: Some(String::from("Independent")),
+                        government: Some(String::from("Fleet Carrier")),
+                        economy: Some(String::from("Fleet Carrier")),
+                        have_market: true,
+                        have_shipyard: false,
+                        have_outfitting: false,
+                        other_services: vec![
+                            String::from("Black Market"),
+                            String::from("Restock"),
+                            String::from("Refuel"),
+                            String::from("Repair"),
+                            String::from("Contacts"),
+                            String::from("Universal Cartographics"),
+                            String::from("Crew Lounge"),
+                        ],
                     },
                     Station {
-                        station_type: Some("Orbis Starport".to_string()),
+                        name: Some(String::from("Apgar Terminal")),
+                        kind: Some("Orbis Starport".to_string()),
                         distance_to_arrival: Some(296.864_456),
+                        allegiance: Some(String::from("Federation")),
+                        government: Some(String::from("Democracy")),
+                        economy: Some(String::from("Terraforming")),
+                        have_market: true,
+                        have_shipyard: true,
+                        have_outfitting: true,
+                        other_services: vec![
+                            String::from("Black Market"),
+                            String::from("Restock"),
+                            String::from("Refuel"),
+                            String::from("Repair"),
+                            String::from("Contacts"),
+                            String::from("Universal Cartographics"),
+                            String::from("Missions"),
+                            String::from("Crew Lounge"),
+                            String::from("Tuning"),
+                            String::from("Search and Rescue"),
+                        ],
                     }
                 ]),
                 allegiance: Some("Federation".parse().unwrap()),
-                government: Some("Corporate".parse().unwrap())
+                government: Some("Corporate".parse().unwrap()),
+                id64: Some(3_932_277_445_322),
+                economy: Some("Agriculture".to_string()),
+                security: Some("High".to_string()),
+                state: Some("None".to_string()),
             }]
         );
     }
@@ -177,11 +552,82 @@ mod tests {
 
         assert_eq!(
             parse(example_json.as_bytes())
+                .next()
                 .unwrap()
-                .get(0)
                 .unwrap()
                 .name(),
             "Sanos"
         );
     }
+
+    #[tokio::test]
+    async fn implements_faction_trait() {
+        let example_json = indoc! {r#"
+            [{"name":"Sol","coords":{"x":0,"y":0,"z":0},"controllingFaction":{"allegiance":"Federation"},"factions":[{"name":"Mother Gaia","allegiance":"Federation","government":"Democracy","influence":0.6,"state":"Boom","activeStates":[{"state":"Boom"},{"state":"Investment"}],"pendingStates":[{"state":"Expansion","trend":0}],"recoveringStates":[{"state":"Election","trend":0}],"happiness":"Elated","isPlayer":false},{"name":"Sol Workers' Party","isPlayer":true}]}]
+        "#};
+
+        let system = parse(example_json.as_bytes()).next().unwrap().unwrap();
+        let factions = system.factions();
+
+        assert_eq!(factions[0].name(), "Mother Gaia");
+        assert_eq!(factions[0].allegiance(), "Federation");
+        assert_eq!(factions[0].government(), "Democracy");
+        assert!((factions[0].influence() - 0.6).abs() < f64::EPSILON);
+        assert_eq!(factions[0].state(), "Boom");
+        assert_eq!(factions[0].active_states(), vec!["Boom", "Investment"]);
+        assert_eq!(factions[0].pending_states(), vec!["Expansion"]);
+        assert_eq!(factions[0].recovering_states(), vec!["Election"]);
+        assert_eq!(factions[0].happiness(), "Elated");
+        assert!(!factions[0].is_player());
+
+        assert_eq!(factions[1].name(), "Sol Workers' Party");
+        assert_eq!(factions[1].allegiance(), "");
+        assert!(factions[1].influence().abs() < f64::EPSILON);
+        assert!(factions[1].active_states().is_empty());
+        assert!(factions[1].is_player());
+    }
+
+    #[tokio::test]
+    async fn implements_controlling_faction_trait() {
+        let example_json = indoc! {r#"
+            [{"name":"Sol","coords":{"x":0,"y":0,"z":0},"controllingFaction":{"name":"Mother Gaia","allegiance":"Federation","government":"Democracy","isPlayer":false}},{"name":"Nowhere","coords":{"x":0,"y":0,"z":0},"controllingFaction":{}}]
+        "#};
+
+        let systems = parse(example_json.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let controlling_faction = systems[0].controlling_faction().unwrap();
+
+        assert_eq!(controlling_faction.name(), "Mother Gaia");
+        assert_eq!(controlling_faction.allegiance(), "Federation");
+        assert_eq!(controlling_faction.government(), "Democracy");
+        assert!(!controlling_faction.is_player());
+        assert!(systems[1].controlling_faction().is_none());
+    }
+
+    #[tokio::test]
+    async fn implements_station_trait() {
+        let example_json = indoc! {r#"
+            [{"name":"Sol","coords":{"x":0,"y":0,"z":0},"controllingFaction":{},"stations":[{"type":"Orbis Starport","name":"Galileo","distanceToArrival":505.4,"allegiance":"Federation","government":"Democracy","economy":"Refinery","haveMarket":true,"haveShipyard":true,"haveOutfitting":false,"otherServices":["Material Trader","Interstellar Factors Contact"]},{"type":"Outpost"}]}]
+        "#};
+
+        let system = parse(example_json.as_bytes()).next().unwrap().unwrap();
+        let stations = system.stations();
+
+        assert_eq!(stations[0].name(), "Galileo");
+        assert_eq!(stations[0].allegiance(), "Federation");
+        assert_eq!(stations[0].government(), "Democracy");
+        assert_eq!(stations[0].economy(), "Refinery");
+        assert!(stations[0].has_market());
+        assert!(stations[0].has_shipyard());
+        assert!(!stations[0].has_outfitting());
+        assert_eq!(
+            stations[0].other_services(),
+            vec!["Material Trader", "Interstellar Factors Contact"]
+        );
+
+        assert_eq!(stations[1].name(), "");
+        assert!(!stations[1].has_market());
+        assert!(stations[1].other_services().is_empty());
+    }
 }
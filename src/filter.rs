@@ -1,20 +1,29 @@
 use futures::{stream, StreamExt};
+use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::future::ready;
 
 use crate::distance;
-use crate::domain::{Coords, System, SystemFilter};
+use crate::domain::{
+    count_stations, has_service, large_dock_types, Allegiance, ControllingFaction, Coords, Economy,
+    Government, Security, Sort, SortField, SortOrder, StateStage, Station, StationFilter,
+    StationType, System, SystemFilter,
+};
+use crate::spatial::KdTree;
 
-pub async fn filter<'a, T: System<'a> + Clone + Send + Sync>(
-    search_options: &'a [SystemFilter<'a>],
-    systems: &'a [T],
-) -> Vec<T> {
-    let mut systems: Vec<_> = stream::iter(systems.iter().cloned())
+pub async fn filter<'a, T, I>(search_options: &'a [SystemFilter<'a>], systems: I) -> Vec<T>
+where
+    T: System<'a> + Clone + Send + Sync,
+    I: IntoIterator<Item = T>,
+{
+    let distances = DistanceIndex::new(search_options);
+    let mut systems: Vec<_> = stream::iter(systems)
         .filter(|system| {
+            let nearby = distances.nearby(&system.coordinates());
             ready(
                 search_options
                     .iter()
-                    .all(|filter| suitable_system(system, filter)),
+                    .all(|filter| suitable_system(system, filter, &nearby)),
             )
         })
         .collect()
@@ -24,86 +33,436 @@ pub async fn filter<'a, T: System<'a> + Clone + Send + Sync>(
     systems
 }
 
+/// The systems closest to the reference, nearest first, leaving out any system at the reference
+/// itself
+pub fn nearest<'a, T: System<'a>>(systems: Vec<T>, reference: &Coords, count: usize) -> Vec<T> {
+    let tree = KdTree::new(
+        systems
+            .iter()
+            .map(System::coordinates)
+            .zip(0_usize..)
+            .filter(|(coordinates, _)| coordinates != reference)
+            .collect(),
+    );
+    let nearest = tree
+        .nearest(reference, count)
+        .into_iter()
+        .map(|(index, _)| *index)
+        .collect::<Vec<_>>();
+
+    let mut systems = systems.into_iter().map(Some).collect::<Vec<_>>();
+    nearest
+        .into_iter()
+        .filter_map(|index| systems[index].take())
+        .collect()
+}
+
+/// Sort the systems by each key in turn, systems that are equal on every key keep their order
+pub fn sort<'a, T: System<'a>>(systems: &mut [T], keys: &[Sort]) {
+    let large_docks = large_dock_types();
+    systems.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| compare(key, a, b, &large_docks))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+fn compare<'a, T: System<'a>>(
+    key: &Sort,
+    a: &T,
+    b: &T,
+    large_docks: &HashSet<StationType>,
+) -> Ordering {
+    let ordering = match key.field {
+        SortField::Name => a.name().cmp(b.name()),
+        SortField::DistanceFrom(reference) => distance::distance(&reference, &a.coordinates())
+            .total_cmp(&distance::distance(&reference, &b.coordinates())),
+        SortField::Population => a.population().cmp(&b.population()),
+        SortField::LargeDocks => {
+            count_stations(a, large_docks).cmp(&count_stations(b, large_docks))
+        }
+        SortField::Factions => a.factions().len().cmp(&b.factions().len()),
+    };
+
+    match key.order {
+        SortOrder::Ascending => ordering,
+        SortOrder::Descending => ordering.reverse(),
+    }
+}
+
+type DistanceKey = [u64; 4];
+
+fn distance_key(reference: &Coords, distance: f64) -> DistanceKey {
+    [
+        reference.x.to_bits(),
+        reference.y.to_bits(),
+        reference.z.to_bits(),
+        distance.to_bits(),
+    ]
+}
+
+/// The reference points of every distance filter, so a system only needs a single radius query to
+/// find which of them it is close enough to
+struct DistanceIndex {
+    references: KdTree<(DistanceKey, f64)>,
+    max_distance: f64,
+}
+
+impl DistanceIndex {
+    fn new(search_options: &[SystemFilter<'_>]) -> Self {
+        let mut references = vec![];
+        for filter in search_options {
+            distance_filters(filter, &mut references);
+        }
+
+        DistanceIndex {
+            max_distance: references
+                .iter()
+                .map(|(_, (_, distance))| *distance)
+                .fold(f64::NEG_INFINITY, f64::max),
+            references: KdTree::new(references),
+        }
+    }
+
+    fn nearby(&self, coordinates: &Coords) -> HashSet<DistanceKey> {
+        if self.references.is_empty() {
+            return HashSet::new();
+        }
+
+        self.references
+            .within(coordinates, self.max_distance)
+            .into_iter()
+            .filter(|((_, max_distance), distance)| distance <= max_distance)
+            .map(|((key, _), _)| *key)
+            .collect()
+    }
+}
+
+fn distance_filters(filter: &SystemFilter<'_>, found: &mut Vec<(Coords, (DistanceKey, f64))>) {
+    match filter {
+        SystemFilter::MaximumDistanceFrom(reference, distance) => {
+            found.push((*reference, (distance_key(reference, *distance), *distance)));
+        }
+        SystemFilter::And(filters) | SystemFilter::Or(filters) => {
+            for filter in filters {
+                distance_filters(filter, found);
+            }
+        }
+        SystemFilter::Not(filter) => distance_filters(filter, found),
+        _ => {}
+    }
+}
+
+/// Whether a station passes every station filter
+pub fn suitable_station(station_filters: &[StationFilter<'_>], station: &dyn Station) -> bool {
+    station_filters.iter().all(|filter| match filter {
+        StationFilter::Criteria(criteria) => station
+            .station_type()
+            .and_then(|station_type| StationType::from(station_type).details())
+            .is_some_and(|details| criteria.matches(details)),
+        StationFilter::Service(service) => has_service(station, service),
+        StationFilter::MaximumDistanceToArrival(light_seconds) => station
+            .distance_to_arrival()
+            .is_some_and(|distance| distance <= *light_seconds),
+    })
+}
+
 fn suitable_system<'a, T: System<'a> + Clone + Send>(
     system: &T,
     filter: &SystemFilter<'_>,
+    nearby: &HashSet<DistanceKey>,
 ) -> bool {
     match filter {
-        SystemFilter::MaximumDistanceFrom(reference, distance_from_reference_ls) => {
-            has_location_within_max_distance_from_reference(
-                *distance_from_reference_ls,
-                reference,
-                system,
-            )
+        SystemFilter::MaximumDistanceFrom(reference, distance_from_reference_ly) => {
+            nearby.contains(&distance_key(reference, *distance_from_reference_ly))
+        }
+        SystemFilter::MinimumDistanceFrom(reference, distance_from_reference_ly) => {
+            has_min_distance_from(reference, *distance_from_reference_ly, system)
         }
         SystemFilter::MinimumStationCount(types, docks) => has_docks(*docks, types, system),
+        SystemFilter::MaximumStationCount(types, stations) => {
+            count_stations(system, types) <= *stations
+        }
+        SystemFilter::MinimumStationsWithService(service, count) => {
+            has_stations_with_service(service, *count, system)
+        }
+        SystemFilter::MinimumStationCountWithin(types, docks, distance_to_arrival_ls) => {
+            has_docks_within(*docks, types, *distance_to_arrival_ls, system)
+        }
         SystemFilter::MaximumFactionCount(factions) => {
             has_max_number_of_factions(*factions, system)
         }
+        SystemFilter::MinimumFactionCount(factions) => {
+            has_min_number_of_factions(*factions, system)
+        }
         SystemFilter::MinimumPopulation(population) => has_min_population(*population, system),
+        SystemFilter::MaximumPopulation(population) => has_max_population(*population, system),
         SystemFilter::ExcludeSystems(systems) => !is_excluded_system(systems, system),
         SystemFilter::ExcludeSystemsWithPlayerFactions => !has_player_faction(system),
+        SystemFilter::FactionPresent(name) => has_faction(name, system),
+        SystemFilter::FactionAbsent(name) => !has_faction(name, system),
+        SystemFilter::MaximumControllingFactionInfluence(influence) => {
+            has_max_controlling_faction_influence(*influence, system)
+        }
+        SystemFilter::MaximumControllingFactionLead(influence) => {
+            has_max_controlling_faction_lead(*influence, system)
+        }
+        SystemFilter::MinimumFactionInfluence(name, influence) => {
+            has_min_faction_influence(name, *influence, system)
+        }
+        SystemFilter::FactionInState(state_name, stage) => {
+            has_faction_in_state(state_name, *stage, system)
+        }
+        SystemFilter::ControlledBy(name) => is_controlled_by(name, system),
+        SystemFilter::ControllingFactionAllegiance(allegiance) => {
+            has_controlling_faction_allegiance(allegiance, system)
+        }
+        SystemFilter::ControllingFactionGovernment(government) => {
+            has_controlling_faction_government(government, system)
+        }
+        SystemFilter::ExcludeSystemsWithPlayerControllingFaction => !is_player_controlled(system),
         SystemFilter::Allegiance(allegiance) => has_allegiance(allegiance, system),
         SystemFilter::Government(government) => has_government(government, system),
+        SystemFilter::AllegianceOneOf(allegiances) => has_allegiance_in(allegiances, system),
+        SystemFilter::GovernmentOneOf(governments) => has_government_in(governments, system),
+        SystemFilter::ExcludeAllegiances(allegiances) => !has_allegiance_in(allegiances, system),
+        SystemFilter::ExcludeGovernments(governments) => !has_government_in(governments, system),
+        SystemFilter::Economy(economy) => has_economy(economy, system),
+        SystemFilter::Security(security) => has_security(security, system),
+        SystemFilter::SystemState(state) => has_system_state(state, system),
+        SystemFilter::And(filters) => filters
+            .iter()
+            .all(|filter| suitable_system(system, filter, nearby)),
+        SystemFilter::Or(filters) => filters
+            .iter()
+            .any(|filter| suitable_system(system, filter, nearby)),
+        SystemFilter::Not(filter) => !suitable_system(system, filter, nearby),
     }
 }
 
+fn has_min_distance_from<'a, T: System<'a>>(
+    reference: &Coords,
+    min_distance: f64,
+    system: &T,
+) -> bool {
+    distance::distance(reference, &system.coordinates()) >= min_distance
+}
+
 fn is_excluded_system<'a, T: System<'a>>(excluded_systems: &HashSet<&str>, system: &T) -> bool {
     excluded_systems.contains(system.name())
 }
-fn has_allegiance<'a, T: System<'a>>(allegiance: &str, system: &T) -> bool {
-    system.allegiance().eq(allegiance)
+fn has_allegiance<'a, T: System<'a>>(allegiance: &Allegiance, system: &T) -> bool {
+    Allegiance::from(system.allegiance()) == *allegiance
+}
+
+fn has_government<'a, T: System<'a>>(government: &Government, system: &T) -> bool {
+    Government::from(system.government()) == *government
+}
+
+fn has_allegiance_in<'a, T: System<'a>>(allegiances: &HashSet<Allegiance>, system: &T) -> bool {
+    allegiances.contains(&Allegiance::from(system.allegiance()))
+}
+
+fn has_government_in<'a, T: System<'a>>(governments: &HashSet<Government>, system: &T) -> bool {
+    governments.contains(&Government::from(system.government()))
+}
+
+fn has_economy<'a, T: System<'a>>(economy: &Economy, system: &T) -> bool {
+    Economy::from(system.economy()) == *economy
+}
+
+fn has_security<'a, T: System<'a>>(security: &Security, system: &T) -> bool {
+    Security::from(system.security()) == *security
+}
+
+fn has_system_state<'a, T: System<'a>>(state: &str, system: &T) -> bool {
+    system.state().eq_ignore_ascii_case(state)
+}
+
+fn has_docks<'a, T: System<'a>>(
+    min_large_docks: usize,
+    types: &HashSet<StationType>,
+    system: &T,
+) -> bool {
+    count_stations(system, types) >= min_large_docks
 }
 
-fn has_government<'a, T: System<'a>>(government: &str, system: &T) -> bool {
-    system.government().eq(government)
+fn has_stations_with_service<'a, T: System<'a>>(service: &str, count: usize, system: &T) -> bool {
+    system
+        .stations()
+        .iter()
+        .filter(|station| has_service(station.as_ref(), service))
+        .count()
+        >= count
 }
 
-fn has_docks<'a, T: System<'a>>(min_large_docks: usize, types: &HashSet<&str>, system: &T) -> bool {
+fn has_docks_within<'a, T: System<'a>>(
+    min_docks: usize,
+    types: &HashSet<StationType>,
+    max_distance_to_arrival_ls: f64,
+    system: &T,
+) -> bool {
     system
         .stations()
         .iter()
-        .map(|x| x.station_type())
         .filter(|x| {
-            matches!(
-                (*x).filter(|station_type| types.contains(station_type)),
-                Some(_)
-            )
+            x.station_type()
+                .is_some_and(|station_type| types.contains(&StationType::from(station_type)))
+        })
+        .filter(|x| {
+            x.distance_to_arrival()
+                .is_some_and(|distance| distance <= max_distance_to_arrival_ls)
         })
         .count()
-        >= min_large_docks
+        >= min_docks
 }
 
 fn has_max_number_of_factions<'a, T: System<'a>>(max_factions: usize, system: &T) -> bool {
     system.factions().len() <= max_factions
 }
 
+fn has_min_number_of_factions<'a, T: System<'a>>(min_factions: usize, system: &T) -> bool {
+    system.factions().len() >= min_factions
+}
+
 fn has_player_faction<'a, T: System<'a>>(system: &T) -> bool {
     system.factions().iter().any(|faction| faction.is_player())
 }
 
-fn has_min_population<'a, T: System<'a>>(min_population: u128, system: &T) -> bool {
-    system.population() >= min_population
+fn has_faction<'a, T: System<'a>>(name: &str, system: &T) -> bool {
+    system
+        .factions()
+        .iter()
+        .any(|faction| faction.name() == name)
 }
 
-fn has_location_within_max_distance_from_reference<'a, T: System<'a>>(
-    distance_from_reference_ls: f64,
-    reference: &Coords,
+fn controlling_faction_influence<'a, T: System<'a>>(system: &T) -> Option<f64> {
+    let controlling_faction = system.controlling_faction()?;
+    system
+        .factions()
+        .iter()
+        .find(|faction| faction.name() == controlling_faction.name())
+        .map(|faction| faction.influence())
+}
+
+fn has_max_controlling_faction_influence<'a, T: System<'a>>(
+    max_influence: f64,
+    system: &T,
+) -> bool {
+    controlling_faction_influence(system).is_some_and(|influence| influence <= max_influence)
+}
+
+/// Whether the closest rival is no more than this much influence behind the controlling faction.
+/// A rival with more influence than the controlling faction counts too, since it's about to take
+/// over
+fn has_max_controlling_faction_lead<'a, T: System<'a>>(max_lead: f64, system: &T) -> bool {
+    let (Some(controlling_faction), Some(controlling_influence)) = (
+        system.controlling_faction(),
+        controlling_faction_influence(system),
+    ) else {
+        return false;
+    };
+
+    system
+        .factions()
+        .iter()
+        .filter(|faction| faction.name() != controlling_faction.name())
+        .any(|faction| controlling_influence - faction.influence() <= max_lead)
+}
+
+fn is_controlled_by<'a, T: System<'a>>(name: &str, system: &T) -> bool {
+    system
+        .controlling_faction()
+        .is_some_and(|controlling_faction| controlling_faction.name() == name)
+}
+
+fn has_controlling_faction_allegiance<'a, T: System<'a>>(
+    allegiance: &Allegiance,
+    system: &T,
+) -> bool {
+    system
+        .controlling_faction()
+        .is_some_and(|controlling_faction| {
+            Allegiance::from(controlling_faction.allegiance()) == *allegiance
+        })
+}
+
+fn has_controlling_faction_government<'a, T: System<'a>>(
+    government: &Government,
+    system: &T,
+) -> bool {
+    system
+        .controlling_faction()
+        .is_some_and(|controlling_faction| {
+            Government::from(controlling_faction.government()) == *government
+        })
+}
+
+fn is_player_controlled<'a, T: System<'a>>(system: &T) -> bool {
+    system
+        .controlling_faction()
+        .is_some_and(ControllingFaction::is_player)
+}
+
+fn has_min_faction_influence<'a, T: System<'a>>(
+    name: &str,
+    min_influence: f64,
+    system: &T,
+) -> bool {
+    system
+        .factions()
+        .iter()
+        .any(|faction| faction.name() == name && faction.influence() >= min_influence)
+}
+
+fn has_faction_in_state<'a, T: System<'a>>(
+    state_name: &str,
+    stage: StateStage,
     system: &T,
 ) -> bool {
-    distance::distance(reference, &system.coordinates()) <= distance_from_reference_ls
+    system.factions().iter().any(|faction| {
+        let states = match stage {
+            StateStage::Pending => faction.pending_states(),
+            StateStage::Active => faction.active_states(),
+            StateStage::Recovering => faction.recovering_states(),
+        };
+        states
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(state_name))
+    })
+}
+
+fn has_min_population<'a, T: System<'a>>(min_population: u128, system: &T) -> bool {
+    system.population() >= min_population
+}
+
+fn has_max_population<'a, T: System<'a>>(max_population: u128, system: &T) -> bool {
+    system.population() <= max_population
 }
 
 #[cfg(test)]
 mod tests {
 
     use crate::domain::{
-        allegiance, exclude_permit_locked, exclude_player_faction, exclude_rare_commodity,
-        government, max_distance_from_reference, max_distance_from_sol, max_number_of_factions,
-        min_docks, min_large_docks, min_population, min_starports,
+        allegiance, and, exclude_permit_locked, exclude_player_faction, exclude_rare_commodity,
+        faction_absent, faction_present, government, max_controlling_faction_influence,
+        max_controlling_faction_lead, max_distance_from_reference, max_distance_from_sol,
+        max_fleet_carriers, max_number_of_factions, max_population, min_distance_from_reference,
+        min_distance_from_sol, min_docks, min_docks_within, min_faction_influence,
+        min_fleet_carriers, min_large_docks, min_large_docks_within, min_number_of_factions,
+        min_population, min_starports, min_starports_within, min_stations,
+        min_stations_with_service, not, or, require_service,
     };
 
-    use crate::filter::filter;
+    use crate::domain::{
+        allegiance_one_of, controlled_by, controlling_faction_allegiance,
+        controlling_faction_government, economy, exclude_allegiances, exclude_governments,
+        exclude_player_controlled, faction_in_state, government_one_of, security, sort_by,
+        system_state, Allegiance, Economy, Government, Security, SortField, SortOrder, StateStage,
+        StationCriteria,
+    };
+    use crate::filter::{filter, nearest, sort, suitable_station, DistanceIndex};
     use crate::stub::Faction;
     use crate::{domain, stub};
 
@@ -120,6 +479,11 @@ mod tests {
             stations: vec![],
             allegiance: String::new(),
             government: String::new(),
+            controlling_faction: None,
+            id64: None,
+            economy: String::new(),
+            security: String::new(),
+            state: String::new(),
         }
     }
 
@@ -136,6 +500,11 @@ mod tests {
             factions: vec![],
             stations: vec![],
             government: String::new(),
+            controlling_faction: None,
+            id64: None,
+            economy: String::new(),
+            security: String::new(),
+            state: String::new(),
         }
     }
 
@@ -148,6 +517,11 @@ mod tests {
                 z: -52.625_f64,
             },
             government: String::from(government),
+            controlling_faction: None,
+            id64: None,
+            economy: String::new(),
+            security: String::new(),
+            state: String::new(),
             allegiance: String::new(),
             population: 0,
             factions: vec![],
@@ -168,6 +542,11 @@ mod tests {
             stations: vec![],
             allegiance: String::new(),
             government: String::new(),
+            controlling_faction: None,
+            id64: None,
+            economy: String::new(),
+            security: String::new(),
+            state: String::new(),
         }
     }
 
@@ -185,10 +564,31 @@ mod tests {
                 .iter()
                 .map(|x| stub::Station {
                     station_type: Some(String::from(*x)),
+                    distance_to_arrival: Some(100.0),
+                    ..stub::Station::default()
                 })
                 .collect(),
             allegiance: String::new(),
             government: String::new(),
+            controlling_faction: None,
+            id64: None,
+            economy: String::new(),
+            security: String::new(),
+            state: String::new(),
+        }
+    }
+
+    fn make_stub_system_with_docks_at(name: &str, docks: &[(&str, f64)]) -> stub::System {
+        stub::System {
+            stations: docks
+                .iter()
+                .map(|(station_type, distance_to_arrival)| stub::Station {
+                    station_type: Some(String::from(*station_type)),
+                    distance_to_arrival: Some(*distance_to_arrival),
+                    ..stub::Station::default()
+                })
+                .collect(),
+            ..make_system(name)
         }
     }
 
@@ -203,11 +603,26 @@ mod tests {
             population: 1,
             factions: factions
                 .iter()
-                .map(|player| Faction { is_player: *player })
+                .map(|player| Faction {
+                    is_player: *player,
+                    ..Faction::default()
+                })
                 .collect(),
             stations: vec![],
             allegiance: String::new(),
             government: String::new(),
+            controlling_faction: None,
+            id64: None,
+            economy: String::new(),
+            security: String::new(),
+            state: String::new(),
+        }
+    }
+
+    fn make_system_with_minor_factions(name: &str, factions: Vec<Faction>) -> stub::System {
+        stub::System {
+            factions,
+            ..make_system(name)
         }
     }
 
@@ -220,13 +635,18 @@ mod tests {
             stations: vec![],
             allegiance: String::new(),
             government: String::new(),
+            controlling_faction: None,
+            id64: None,
+            economy: String::new(),
+            security: String::new(),
+            state: String::new(),
         }
     }
 
     #[tokio::test]
     async fn no_options_returns_everything() {
         let input = [make_system("Sanos"), make_system("Sol")];
-        assert_eq!(filter(&[], &input).await, input);
+        assert_eq!(filter(&[], input.clone()).await, input);
     }
 
     #[tokio::test]
@@ -254,7 +674,7 @@ mod tests {
             ),
             sol.clone(),
         ];
-        assert_eq!(filter(&[min_large_docks(5)], &input,).await, &[sol]);
+        assert_eq!(filter(&[min_large_docks(5)], input).await, &[sol]);
     }
 
     #[tokio::test]
@@ -270,7 +690,7 @@ mod tests {
             ),
             sol.clone(),
         ];
-        assert_eq!(filter(&[min_starports(3)], &input,).await, &[sol]);
+        assert_eq!(filter(&[min_starports(3)], input).await, &[sol]);
     }
 
     #[tokio::test]
@@ -280,7 +700,194 @@ mod tests {
             make_stub_system_with_docks("Sanos", &["Planetary Outpost"]),
             sol.clone(),
         ];
-        assert_eq!(filter(&[min_docks(2)], &input,).await, &[sol]);
+        assert_eq!(filter(&[min_docks(2)], input).await, &[sol]);
+    }
+
+    #[tokio::test]
+    async fn systems_without_enough_stations_matching_the_criteria_are_skipped() {
+        let sol = make_stub_system_with_docks("Sol", &["Fleet Carrier", "Mega ship"]);
+        let input = [
+            make_stub_system_with_docks("Sanos", &["Fleet Carrier", "Orbis Starport"]),
+            sol.clone(),
+        ];
+        let transient = StationCriteria {
+            permanent: Some(false),
+            ..StationCriteria::default()
+        };
+        assert_eq!(filter(&[min_stations(transient, 2)], input).await, &[sol]);
+    }
+
+    #[tokio::test]
+    async fn systems_with_too_many_fleet_carriers_are_skipped() {
+        let sol = make_stub_system_with_docks("Sol", &["Fleet Carrier", "Orbis Starport"]);
+        let input = [
+            make_stub_system_with_docks("Sanos", &["Fleet Carrier", "Fleet Carrier"]),
+            sol.clone(),
+        ];
+        assert_eq!(
+            filter(&[max_fleet_carriers(1)], input.clone()).await,
+            vec![sol]
+        );
+        assert_eq!(
+            filter(&[max_fleet_carriers(0)], input).await,
+            Vec::<stub::System>::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_without_enough_fleet_carriers_are_skipped() {
+        let sanos = make_stub_system_with_docks("Sanos", &["Fleet Carrier", "Fleet Carrier"]);
+        let input = [
+            sanos.clone(),
+            make_stub_system_with_docks("Sol", &["Fleet Carrier", "Orbis Starport"]),
+        ];
+        assert_eq!(filter(&[min_fleet_carriers(2)], input).await, &[sanos]);
+    }
+
+    #[tokio::test]
+    async fn systems_without_enough_large_docks_near_the_arrival_star_are_skipped() {
+        let sol = make_stub_system_with_docks_at(
+            "Sol",
+            &[("Orbis Starport", 500.0), ("Coriolis Starport", 1000.0)],
+        );
+        let input = [
+            make_stub_system_with_docks_at(
+                "Sanos",
+                &[("Orbis Starport", 500.0), ("Coriolis Starport", 200_000.0)],
+            ),
+            sol.clone(),
+        ];
+        assert_eq!(
+            filter(&[min_large_docks_within(2, 1000.0)], input).await,
+            &[sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn stations_without_a_distance_do_not_count_towards_docks_near_the_arrival_star() {
+        let sol = make_stub_system_with_docks_at("Sol", &[("Outpost", 10.0)]);
+        let input = [
+            stub::System {
+                stations: vec![stub::Station {
+                    station_type: Some(String::from("Outpost")),
+                    distance_to_arrival: None,
+                    ..stub::Station::default()
+                }],
+                ..make_system("Sanos")
+            },
+            sol.clone(),
+        ];
+        assert_eq!(filter(&[min_docks_within(1, 1000.0)], input).await, &[sol]);
+    }
+
+    #[tokio::test]
+    async fn systems_without_enough_starports_near_the_arrival_star_are_skipped() {
+        let sol = make_stub_system_with_docks_at("Sol", &[("Orbis Starport", 10.0)]);
+        let input = [
+            make_stub_system_with_docks_at("Sanos", &[("Asteroid base", 10.0)]),
+            sol.clone(),
+        ];
+        assert_eq!(
+            filter(&[min_starports_within(1, 1000.0)], input).await,
+            &[sol]
+        );
+    }
+
+    fn make_system_with_stations(name: &str, stations: Vec<stub::Station>) -> stub::System {
+        stub::System {
+            stations,
+            ..make_system(name)
+        }
+    }
+
+    #[tokio::test]
+    async fn systems_without_a_station_offering_a_service_are_skipped() {
+        let sol = make_system_with_stations(
+            "Sol",
+            vec![stub::Station {
+                has_shipyard: true,
+                other_services: vec![String::from("Material Trader")],
+                ..stub::Station::default()
+            }],
+        );
+        let input = [
+            make_system_with_stations(
+                "Sanos",
+                vec![stub::Station {
+                    has_market: true,
+                    other_services: vec![String::from("Refuel")],
+                    ..stub::Station::default()
+                }],
+            ),
+            sol.clone(),
+        ];
+
+        assert_eq!(
+            filter(&[require_service("material trader")], input.clone()).await,
+            vec![sol.clone()]
+        );
+        assert_eq!(
+            filter(&[require_service("Shipyard")], input).await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_without_enough_stations_offering_a_service_are_skipped() {
+        let shipyard = || stub::Station {
+            has_shipyard: true,
+            ..stub::Station::default()
+        };
+        let sol = make_system_with_stations("Sol", vec![shipyard(), shipyard()]);
+        let input = [
+            make_system_with_stations("Sanos", vec![shipyard(), stub::Station::default()]),
+            sol.clone(),
+        ];
+
+        assert_eq!(
+            filter(&[min_stations_with_service("Shipyard", 2)], input).await,
+            vec![sol]
+        );
+    }
+
+    #[test]
+    fn stations_are_filtered_on_their_own() {
+        let carrier = stub::Station {
+            station_type: Some(String::from("Fleet Carrier")),
+            distance_to_arrival: Some(10.0),
+            other_services: vec![String::from("Refuel")],
+            ..stub::Station::default()
+        };
+        let outpost = stub::Station {
+            station_type: Some(String::from("Outpost")),
+            distance_to_arrival: Some(5000.0),
+            has_shipyard: true,
+            ..stub::Station::default()
+        };
+        let large_pads = [domain::station_matching(StationCriteria {
+            pad: Some(domain::PadSize::Large),
+            ..StationCriteria::default()
+        })];
+
+        assert!(suitable_station(&[], &outpost));
+        assert!(suitable_station(&large_pads, &carrier));
+        assert!(!suitable_station(&large_pads, &outpost));
+        assert!(suitable_station(
+            &[domain::station_with_service("shipyard")],
+            &outpost
+        ));
+        assert!(!suitable_station(
+            &[domain::station_with_service("Shipyard")],
+            &carrier
+        ));
+        assert!(suitable_station(
+            &[domain::max_distance_to_arrival(1000.0)],
+            &carrier
+        ));
+        assert!(!suitable_station(
+            &[domain::max_distance_to_arrival(1000.0)],
+            &outpost
+        ));
     }
 
     #[tokio::test]
@@ -306,19 +913,68 @@ mod tests {
             sol.clone(),
         ];
         assert_eq!(
-            filter(&[max_distance_from_sol(90.0)], &input).await,
+            filter(&[max_distance_from_sol(90.0)], input).await,
             vec![sol]
         );
     }
 
+    #[tokio::test]
+    async fn systems_too_close_to_the_reference_skipped() {
+        let sanos = make_system_at_coords(
+            "Sanos",
+            domain::Coords {
+                x: 73.875_f64,
+                y: -3.5625_f64,
+                z: -52.625_f64,
+            },
+        );
+        let input = [
+            sanos.clone(),
+            make_system_at_coords(
+                "Sol",
+                domain::Coords {
+                    x: f64::from(0),
+                    y: f64::from(0),
+                    z: f64::from(0),
+                },
+            ),
+        ];
+        assert_eq!(
+            filter(&[min_distance_from_sol(90.0)], input.clone()).await,
+            vec![sanos.clone()]
+        );
+        assert_eq!(
+            filter(
+                &[
+                    min_distance_from_reference(
+                        domain::Coords {
+                            x: 70.0,
+                            y: 0.0,
+                            z: -50.0,
+                        },
+                        10.0,
+                    ),
+                    max_distance_from_sol(100.0),
+                ],
+                input,
+            )
+            .await,
+            vec![make_system_at_coords(
+                "Sol",
+                domain::Coords {
+                    x: f64::from(0),
+                    y: f64::from(0),
+                    z: f64::from(0),
+                },
+            )]
+        );
+    }
+
     #[tokio::test]
     async fn permit_locked_systems_skipped() {
         let sanos = make_system("Sanos");
         let input = [sanos.clone(), make_system("Sol")];
-        assert_eq!(
-            filter(&[exclude_permit_locked()], &input).await,
-            vec![sanos]
-        );
+        assert_eq!(filter(&[exclude_permit_locked()], input).await, vec![sanos]);
     }
 
     #[tokio::test]
@@ -326,7 +982,7 @@ mod tests {
         let sanos = make_system("Sanos");
         let input = [sanos.clone(), make_system("Alpha Centauri")];
         assert_eq!(
-            filter(&[exclude_rare_commodity()], &input).await,
+            filter(&[exclude_rare_commodity()], input).await,
             vec![sanos]
         );
     }
@@ -362,7 +1018,7 @@ mod tests {
                     },
                     90.0,
                 )],
-                &input,
+                input,
             )
             .await,
             vec![sol]
@@ -374,24 +1030,44 @@ mod tests {
         let sol = make_system_with_population("Sol", 10000_u128);
         let input = [make_system_with_population("Sanos", 9999_u128), sol.clone()];
         assert_eq!(
-            filter(&[min_population(10000_u128)], &input).await,
+            filter(&[min_population(10000_u128)], input).await,
             vec![sol]
         );
     }
 
     #[tokio::test]
-    async fn systems_with_too_many_factions_are_ignored_ignored() {
-        let sol = make_system_with_factions("Sol", &[false, false, false]);
+    async fn systems_with_too_high_population_are_ignored() {
+        let sol = make_system_with_population("Sol", 10000_u128);
         let input = [
-            make_system_with_factions("Sanos", &[false, false, false, false]),
+            make_system_with_population("Sanos", 10001_u128),
             sol.clone(),
         ];
         assert_eq!(
-            filter(&[max_number_of_factions(3)], &input).await,
+            filter(&[max_population(10000_u128)], input).await,
             vec![sol]
         );
     }
 
+    #[tokio::test]
+    async fn systems_with_too_many_factions_are_ignored_ignored() {
+        let sol = make_system_with_factions("Sol", &[false, false, false]);
+        let input = [
+            make_system_with_factions("Sanos", &[false, false, false, false]),
+            sol.clone(),
+        ];
+        assert_eq!(filter(&[max_number_of_factions(3)], input).await, vec![sol]);
+    }
+
+    #[tokio::test]
+    async fn systems_with_too_few_factions_are_ignored() {
+        let sol = make_system_with_factions("Sol", &[false, false, false]);
+        let input = [
+            make_system_with_factions("Sanos", &[false, false]),
+            sol.clone(),
+        ];
+        assert_eq!(filter(&[min_number_of_factions(3)], input).await, vec![sol]);
+    }
+
     #[tokio::test]
     async fn systems_with_player_factions_are_ignored_ignored() {
         let sol = make_system_with_factions("Sol", &[false, false]);
@@ -399,7 +1075,307 @@ mod tests {
             make_system_with_factions("Sanos", &[false, true]),
             sol.clone(),
         ];
-        assert_eq!(filter(&[exclude_player_faction()], &input).await, vec![sol]);
+        assert_eq!(filter(&[exclude_player_faction()], input).await, vec![sol]);
+    }
+
+    #[tokio::test]
+    async fn systems_with_and_without_a_faction() {
+        let faction = |name: &str| Faction {
+            name: String::from(name),
+            ..Faction::default()
+        };
+        let sol = make_system_with_minor_factions(
+            "Sol",
+            vec![faction("Mother Gaia"), faction("Sol Workers' Party")],
+        );
+        let sanos = make_system_with_minor_factions("Sanos", vec![faction("Mother Gaia")]);
+        let input = [sanos.clone(), sol.clone()];
+
+        assert_eq!(
+            filter(&[faction_present("Sol Workers' Party")], input.clone()).await,
+            vec![sol]
+        );
+        assert_eq!(
+            filter(&[faction_absent("Sol Workers' Party")], input.clone()).await,
+            vec![sanos]
+        );
+        assert_eq!(
+            filter(&[faction_absent("Mother Gaia")], input).await,
+            vec![]
+        );
+    }
+
+    fn make_system_with_influence(
+        name: &str,
+        controlling_faction: &str,
+        influence: &[(&str, f64)],
+    ) -> stub::System {
+        stub::System {
+            controlling_faction: Some(stub::ControllingFaction {
+                name: String::from(controlling_faction),
+                ..stub::ControllingFaction::default()
+            }),
+            ..make_system_with_minor_factions(
+                name,
+                influence
+                    .iter()
+                    .map(|(name, influence)| Faction {
+                        name: String::from(*name),
+                        influence: *influence,
+                        ..Faction::default()
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn systems_with_too_much_controlling_faction_influence_skipped() {
+        let sol = make_system_with_influence(
+            "Sol",
+            "Mother Gaia",
+            &[("Mother Gaia", 0.3), ("Sol Workers' Party", 0.4)],
+        );
+        let input = [
+            make_system_with_influence(
+                "Sanos",
+                "Federal Liberal Command",
+                &[
+                    ("Federal Liberal Command", 0.6),
+                    ("Independents of Sanos", 0.4),
+                ],
+            ),
+            make_system_with_influence("Nowhere", "Missing", &[("Independents", 0.1)]),
+            sol.clone(),
+        ];
+
+        assert_eq!(
+            filter(&[max_controlling_faction_influence(0.5)], input).await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_where_the_controlling_faction_is_too_far_ahead_skipped() {
+        let sanos = make_system_with_influence(
+            "Sanos",
+            "Federal Liberal Command",
+            &[
+                ("Federal Liberal Command", 0.45),
+                ("Independents of Sanos", 0.4),
+                ("Sanos Purple Crew", 0.15),
+            ],
+        );
+        let input = [
+            sanos.clone(),
+            make_system_with_influence(
+                "Sol",
+                "Mother Gaia",
+                &[("Mother Gaia", 0.7), ("Sol Workers' Party", 0.3)],
+            ),
+            make_system_with_influence("Alone", "Alone Faction", &[("Alone Faction", 1.0)]),
+        ];
+
+        assert_eq!(
+            filter(&[max_controlling_faction_lead(0.1)], input).await,
+            vec![sanos]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_where_a_rival_is_ahead_of_the_controlling_faction_kept() {
+        let sanos = make_system_with_influence(
+            "Sanos",
+            "Federal Liberal Command",
+            &[
+                ("Federal Liberal Command", 0.3),
+                ("Independents of Sanos", 0.7),
+            ],
+        );
+
+        assert_eq!(
+            filter(&[max_controlling_faction_lead(0.0)], vec![sanos.clone()]).await,
+            vec![sanos]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_where_a_faction_has_too_little_influence_skipped() {
+        let sol = make_system_with_influence(
+            "Sol",
+            "Mother Gaia",
+            &[("Mother Gaia", 0.7), ("Sol Workers' Party", 0.3)],
+        );
+        let input = [
+            make_system_with_influence(
+                "Sanos",
+                "Mother Gaia",
+                &[("Mother Gaia", 0.9), ("Sol Workers' Party", 0.1)],
+            ),
+            make_system_with_influence("Barnard's Star", "Mother Gaia", &[("Mother Gaia", 1.0)]),
+            sol.clone(),
+        ];
+
+        assert_eq!(
+            filter(&[min_faction_influence("Sol Workers' Party", 0.25)], input).await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_with_a_faction_in_a_state() {
+        let sol = make_system_with_minor_factions(
+            "Sol",
+            vec![
+                Faction {
+                    active_states: vec![String::from("Boom")],
+                    pending_states: vec![String::from("Expansion")],
+                    ..Faction::default()
+                },
+                Faction {
+                    active_states: vec![String::from("War")],
+                    recovering_states: vec![String::from("Election")],
+                    ..Faction::default()
+                },
+            ],
+        );
+        let sanos = make_system_with_minor_factions(
+            "Sanos",
+            vec![Faction {
+                pending_states: vec![String::from("War")],
+                ..Faction::default()
+            }],
+        );
+        let input = [sanos.clone(), sol.clone()];
+
+        assert_eq!(
+            filter(
+                &[faction_in_state("War", StateStage::Active)],
+                input.clone()
+            )
+            .await,
+            vec![sol.clone()]
+        );
+        assert_eq!(
+            filter(
+                &[faction_in_state("War", StateStage::Pending)],
+                input.clone()
+            )
+            .await,
+            vec![sanos]
+        );
+        assert_eq!(
+            filter(
+                &[faction_in_state("Election", StateStage::Recovering)],
+                input.clone()
+            )
+            .await,
+            vec![sol]
+        );
+        assert_eq!(
+            filter(&[faction_in_state("Election", StateStage::Active)], input).await,
+            vec![]
+        );
+    }
+
+    #[tokio::test]
+    async fn faction_states_ignore_case() {
+        let sol = make_system_with_minor_factions(
+            "Sol",
+            vec![Faction {
+                recovering_states: vec![String::from("Infrastructure Failure")],
+                ..Faction::default()
+            }],
+        );
+
+        assert_eq!(
+            filter(
+                &[faction_in_state(
+                    "infrastructure failure",
+                    StateStage::Recovering
+                )],
+                vec![sol.clone()]
+            )
+            .await,
+            vec![sol]
+        );
+    }
+
+    fn make_system_controlled_by(
+        name: &str,
+        controlling_faction: &str,
+        player: bool,
+    ) -> stub::System {
+        stub::System {
+            controlling_faction: Some(stub::ControllingFaction {
+                name: String::from(controlling_faction),
+                is_player: player,
+                ..stub::ControllingFaction::default()
+            }),
+            ..make_system_with_factions(name, &[player, true])
+        }
+    }
+
+    #[tokio::test]
+    async fn systems_controlled_by_a_faction() {
+        let sol = make_system_controlled_by("Sol", "Mother Gaia", false);
+        let input = [
+            make_system_controlled_by("Sanos", "Federal Liberal Command", true),
+            make_system("Nowhere"),
+            sol.clone(),
+        ];
+
+        assert_eq!(
+            filter(&[controlled_by("Mother Gaia")], input).await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_by_controlling_faction_allegiance_and_government() {
+        let sol = stub::System {
+            controlling_faction: Some(stub::ControllingFaction {
+                name: String::from("Mother Gaia"),
+                allegiance: String::from("Federation"),
+                government: String::from("Democracy"),
+                is_player: false,
+            }),
+            ..make_system("Sol")
+        };
+        let input = [
+            make_system_controlled_by("Sanos", "Federal Liberal Command", true),
+            make_system("Nowhere"),
+            sol.clone(),
+        ];
+
+        assert_eq!(
+            filter(
+                &[
+                    controlling_faction_allegiance(Allegiance::Federation),
+                    controlling_faction_government(Government::Democracy)
+                ],
+                input
+            )
+            .await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_controlled_by_a_player_faction_are_ignored() {
+        let sol = make_system_controlled_by("Sol", "Mother Gaia", false);
+        let nowhere = make_system("Nowhere");
+        let input = [
+            make_system_controlled_by("Sanos", "Federal Liberal Command", true),
+            nowhere.clone(),
+            sol.clone(),
+        ];
+
+        assert_eq!(
+            filter(&[exclude_player_controlled()], input.clone()).await,
+            vec![nowhere, sol]
+        );
+        assert_eq!(filter(&[exclude_player_faction()], input).await.len(), 1);
     }
 
     #[tokio::test]
@@ -409,7 +1385,10 @@ mod tests {
             make_system_with_allegiance("Sanos", "Federation"),
             sol.clone(),
         ];
-        assert_eq!(filter(&[allegiance("Alliance")], &input).await, vec![sol]);
+        assert_eq!(
+            filter(&[allegiance(Allegiance::Alliance)], input).await,
+            vec![sol]
+        );
     }
 
     #[tokio::test]
@@ -419,6 +1398,385 @@ mod tests {
             make_system_with_government("Sanos", "Corporate"),
             sol.clone(),
         ];
-        assert_eq!(filter(&[government("Democracy")], &input).await, vec![sol]);
+        assert_eq!(
+            filter(&[government(Government::Democracy)], input).await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_with_any_of_several_governments() {
+        let sol = make_system_with_government("Sol", "Democracy");
+        let sanos = make_system_with_government("Sanos", "Confederacy");
+        let input = [
+            make_system_with_government("Duamta", "Anarchy"),
+            sol.clone(),
+            sanos.clone(),
+        ];
+        assert_eq!(
+            filter(
+                &[government_one_of(
+                    vec![Government::Democracy, Government::Confederacy]
+                        .into_iter()
+                        .collect()
+                )],
+                input.clone()
+            )
+            .await,
+            vec![sanos, sol.clone()]
+        );
+        assert_eq!(
+            filter(
+                &[exclude_governments(
+                    vec![Government::Anarchy, Government::Confederacy]
+                        .into_iter()
+                        .collect()
+                )],
+                input
+            )
+            .await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_with_any_of_several_allegiances() {
+        let sol = make_system_with_allegiance("Sol", "Alliance");
+        let sanos = make_system_with_allegiance("Sanos", "Federation");
+        let input = [
+            make_system_with_allegiance("Achenar", "Empire"),
+            sol.clone(),
+            sanos.clone(),
+        ];
+        assert_eq!(
+            filter(
+                &[allegiance_one_of(
+                    vec![Allegiance::Alliance, Allegiance::Federation]
+                        .into_iter()
+                        .collect()
+                )],
+                input.clone()
+            )
+            .await,
+            vec![sanos, sol.clone()]
+        );
+        assert_eq!(
+            filter(
+                &[exclude_allegiances(
+                    vec![Allegiance::Empire, Allegiance::Federation]
+                        .into_iter()
+                        .collect()
+                )],
+                input
+            )
+            .await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn allegiance_and_government_ignore_case() {
+        let sol = make_system_with_allegiance("Sol", "alliance");
+        let input = [
+            make_system_with_allegiance("Sanos", "FEDERATION"),
+            sol.clone(),
+        ];
+        assert_eq!(
+            filter(&[allegiance(Allegiance::Alliance)], input).await,
+            vec![sol]
+        );
+
+        let sol = make_system_with_government("Sol", "prison COLONY");
+        assert_eq!(
+            filter(
+                &[government(Government::PrisonColony)],
+                [
+                    make_system_with_government("Sanos", "Corporate"),
+                    sol.clone()
+                ]
+            )
+            .await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn values_the_game_added_later_still_match() {
+        let sol = make_system_with_government("Sol", "Colony");
+        let input = [
+            make_system_with_government("Sanos", "Corporate"),
+            sol.clone(),
+        ];
+        assert_eq!(
+            filter(&[government("Colony".into())], input).await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_economy_security_and_state() {
+        let sanos = stub::System {
+            economy: String::from("Agriculture"),
+            security: String::from("High"),
+            state: String::from("None"),
+            ..make_system("Sanos")
+        };
+        let sol = stub::System {
+            economy: String::from("Refinery"),
+            security: String::from("High"),
+            state: String::from("Boom"),
+            ..make_system("Sol")
+        };
+        let input = [sanos.clone(), sol.clone()];
+
+        assert_eq!(
+            filter(&[economy(Economy::Agriculture)], input.clone()).await,
+            vec![sanos]
+        );
+        assert_eq!(
+            filter(
+                &[security(Security::High), system_state("Boom")],
+                input.clone()
+            )
+            .await,
+            vec![sol.clone()]
+        );
+        assert_eq!(
+            filter(&[system_state("boom")], input.clone()).await,
+            vec![sol]
+        );
+        assert_eq!(filter(&[security(Security::Low)], input).await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn systems_matching_either_government() {
+        let sol = make_system_with_government("Sol", "Democracy");
+        let sanos = make_system_with_government("Sanos", "Corporate");
+        let input = [
+            sanos.clone(),
+            make_system_with_government("Alioth", "Feudal"),
+            sol.clone(),
+        ];
+        assert_eq!(
+            filter(
+                &[or(vec![
+                    government(Government::Democracy),
+                    government(Government::Corporate)
+                ])],
+                input
+            )
+            .await,
+            vec![sanos, sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_matching_every_filter() {
+        let sol = make_system_with_government("Sol", "Democracy");
+        let input = [make_system_with_population("Sanos", 10), sol.clone()];
+        assert_eq!(
+            filter(
+                &[and(vec![
+                    government(Government::Democracy),
+                    not(min_population(1))
+                ])],
+                input
+            )
+            .await,
+            vec![sol]
+        );
+    }
+
+    #[tokio::test]
+    async fn systems_matching_a_negated_filter_are_skipped() {
+        let sol = make_system_with_factions("Sol", &[false, true]);
+        let input = [make_system_with_factions("Sanos", &[false]), sol.clone()];
+        assert_eq!(
+            filter(&[not(exclude_player_faction())], input).await,
+            vec![sol]
+        );
+    }
+
+    #[test]
+    fn sort_by_distance() {
+        let sol = make_system_at_coords(
+            "Sol",
+            domain::Coords {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        );
+        let sanos = make_system("Sanos");
+        let mut systems = [sanos.clone(), sol.clone()];
+
+        sort(
+            &mut systems,
+            &[sort_by(
+                SortField::DistanceFrom(domain::Coords {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                }),
+                SortOrder::Ascending,
+            )],
+        );
+
+        assert_eq!(systems, [sol, sanos]);
+    }
+
+    #[test]
+    fn sort_descending() {
+        let sol = make_system_with_population("Sol", 100);
+        let sanos = make_system_with_population("Sanos", 10);
+        let mut systems = [sanos.clone(), sol.clone()];
+
+        sort(
+            &mut systems,
+            &[sort_by(SortField::Population, SortOrder::Descending)],
+        );
+
+        assert_eq!(systems, [sol, sanos]);
+    }
+
+    #[test]
+    fn sort_ties_are_broken_by_the_next_key() {
+        let sol = make_system_with_factions("Sol", &[false, false]);
+        let alioth = make_system_with_factions("Alioth", &[false, false]);
+        let sanos = make_system_with_factions("Sanos", &[false]);
+        let mut systems = [sol.clone(), sanos.clone(), alioth.clone()];
+
+        sort(
+            &mut systems,
+            &[
+                sort_by(SortField::Factions, SortOrder::Descending),
+                sort_by(SortField::Name, SortOrder::Ascending),
+            ],
+        );
+
+        assert_eq!(systems, [alioth, sol, sanos]);
+    }
+
+    #[test]
+    fn sort_by_large_docks_keeps_order_of_ties() {
+        let sol = make_stub_system_with_docks("Sol", &["Orbis Starport"]);
+        let alioth = make_stub_system_with_docks("Alioth", &["Outpost"]);
+        let sanos = make_stub_system_with_docks("Sanos", &["Outpost"]);
+        let mut systems = [alioth.clone(), sanos.clone(), sol.clone()];
+
+        sort(
+            &mut systems,
+            &[sort_by(SortField::LargeDocks, SortOrder::Descending)],
+        );
+
+        assert_eq!(systems, [sol, alioth, sanos]);
+    }
+
+    #[tokio::test]
+    async fn systems_near_any_of_several_references() {
+        let sol = make_system_at_coords(
+            "Sol",
+            domain::Coords {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        );
+        let sanos = make_system("Sanos");
+        let input = [
+            make_system_at_coords(
+                "Colonia",
+                domain::Coords {
+                    x: -9530.5,
+                    y: -910.28125,
+                    z: 19808.125,
+                },
+            ),
+            sanos.clone(),
+            sol.clone(),
+        ];
+        assert_eq!(
+            filter(
+                &[or(vec![
+                    max_distance_from_sol(10.0),
+                    max_distance_from_reference(
+                        domain::Coords {
+                            x: 70.0,
+                            y: 0.0,
+                            z: -50.0,
+                        },
+                        10.0
+                    ),
+                    not(max_distance_from_sol(1_000_000.0)),
+                ])],
+                input
+            )
+            .await,
+            vec![sanos, sol]
+        );
+    }
+
+    #[test]
+    fn nothing_is_nearby_without_distance_filters() {
+        let index = DistanceIndex::new(&[min_population(1)]);
+        assert!(index.nearby(&make_system("Sanos").coords).is_empty());
+    }
+
+    #[test]
+    fn nearest_systems_to_the_reference() {
+        let sol = make_system_at_coords(
+            "Sol",
+            domain::Coords {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        );
+        let alpha_centauri = make_system_at_coords(
+            "Alpha Centauri",
+            domain::Coords {
+                x: 3.031_25,
+                y: -0.093_75,
+                z: 3.156_25,
+            },
+        );
+        let input = vec![alpha_centauri.clone(), make_system("Sanos"), sol.clone()];
+
+        assert_eq!(
+            nearest(
+                input,
+                &domain::Coords {
+                    x: 1.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                2
+            ),
+            vec![sol, alpha_centauri]
+        );
+    }
+
+    #[test]
+    fn nearest_systems_leave_out_the_reference() {
+        let sol = make_system_at_coords(
+            "Sol",
+            domain::Coords {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        );
+        let alpha_centauri = make_system_at_coords(
+            "Alpha Centauri",
+            domain::Coords {
+                x: 3.031_25,
+                y: -0.093_75,
+                z: 3.156_25,
+            },
+        );
+        let sanos = make_system("Sanos");
+        let input = vec![alpha_centauri.clone(), sanos.clone(), sol.clone()];
+
+        assert_eq!(nearest(input, &sol.coords, 2), vec![alpha_centauri, sanos]);
     }
 }
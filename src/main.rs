@@ -13,10 +13,13 @@
 
 use std::fs::File;
 use std::io;
+use std::path::Path;
 
-use crate::cli::parameters_from_matches;
 use crate::cli::Cli;
-use crate::domain::System;
+use crate::cli::{
+    locate_from_matches, nearest_from_matches, parameters_from_matches, reference_from_matches,
+    sort_from_matches, station_parameters_from_matches, within_from_matches,
+};
 use flate2::read::GzDecoder;
 use miette::{set_panic_hook, IntoDiagnostic, Result};
 use thiserror::Error as ThisError;
@@ -26,6 +29,10 @@ mod distance;
 mod domain;
 mod edsm;
 mod filter;
+mod output;
+mod query;
+mod spatial;
+#[cfg(test)]
 mod stub;
 use clap::Parser;
 
@@ -33,22 +40,65 @@ use clap::Parser;
 async fn main() -> Result<()> {
     set_panic_hook();
     let args = Cli::parse();
-    let compressed_file = File::open(&args.edsm_path).into_diagnostic()?;
-    let file = GzDecoder::new(compressed_file);
-    let systems = edsm::parse(file).into_diagnostic()?;
 
-    let search_parameters = parameters_from_matches(&args, systems.as_slice()).into_diagnostic()?;
-    let filtered_system = filter::filter(&search_parameters, systems.as_slice());
+    let mut parse_error = None;
+    let located = locate_from_matches(
+        &args,
+        edsm::until_error(open_dump(&args.edsm_path)?, &mut parse_error),
+    );
+    if let Some(error) = parse_error {
+        return Err(error).into_diagnostic();
+    }
+    let located = located.into_diagnostic()?;
+    let reference = reference_from_matches(&args, &located);
+    let mut search_parameters = parameters_from_matches(&args, reference).into_diagnostic()?;
+    search_parameters.extend(within_from_matches(&args, &located));
+    let sort_keys = sort_from_matches(&args, reference).into_diagnostic()?;
+    let nearest = nearest_from_matches(&args, reference).into_diagnostic()?;
+
+    let mut parse_error = None;
+    let mut filtered_system = filter::filter(
+        &search_parameters,
+        edsm::until_error(open_dump(&args.edsm_path)?, &mut parse_error),
+    )
+    .await;
+    if let Some(error) = parse_error {
+        return Err(error).into_diagnostic();
+    }
+
+    if let Some((reference, count)) = nearest {
+        filtered_system = filter::nearest(filtered_system, &reference, count);
+    }
+    filter::sort(&mut filtered_system, &sort_keys);
+    if let Some(limit) = args.limit {
+        filtered_system.truncate(limit);
+    }
 
-    display_systems(filtered_system.await);
+    if args.factions {
+        output::display_factions(args.output, &filtered_system, io::stdout().lock())
+    } else if args.per_station {
+        output::display_stations(
+            args.output,
+            &filtered_system,
+            &station_parameters_from_matches(&args),
+            io::stdout().lock(),
+        )
+    } else {
+        output::display(
+            args.output,
+            &filtered_system,
+            nearest.map(|(reference, _)| reference),
+            io::stdout().lock(),
+        )
+    }
+    .into_diagnostic()?;
 
     Ok(())
 }
 
-fn display_systems<'a, T: System<'a>>(systems: Vec<T>) {
-    for system in systems {
-        println!("{}", system.name());
-    }
+fn open_dump(edsm_path: &Path) -> Result<edsm::Systems<GzDecoder<File>>> {
+    let compressed_file = File::open(edsm_path).into_diagnostic()?;
+    Ok(edsm::parse(GzDecoder::new(compressed_file)))
 }
 
 /// General error type
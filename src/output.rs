@@ -0,0 +1,951 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use clap::ArgEnum;
+use serde::Serialize;
+use thiserror::Error as ThisError;
+
+use crate::distance::distance;
+use crate::domain::{
+    count_station_types, dock_types, fleet_carrier_types, large_dock_types, services,
+    starport_types, Coords, Faction, Station, StationFilter, StationType, System,
+};
+use crate::filter::suitable_station;
+
+#[derive(ArgEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Names,
+    Json,
+    Ndjson,
+    Csv,
+    Table,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct FactionSummary {
+    name: String,
+    allegiance: String,
+    government: String,
+    influence: f64,
+    state: String,
+    active_states: Vec<String>,
+    pending_states: Vec<String>,
+    recovering_states: Vec<String>,
+    happiness: String,
+    is_player: bool,
+}
+
+impl FactionSummary {
+    fn from_faction(faction: &dyn Faction) -> Self {
+        let owned = |states: Vec<&str>| states.into_iter().map(String::from).collect();
+        FactionSummary {
+            name: faction.name().to_string(),
+            allegiance: faction.allegiance().to_string(),
+            government: faction.government().to_string(),
+            influence: percent(faction.influence()),
+            state: faction.state().to_string(),
+            active_states: owned(faction.active_states()),
+            pending_states: owned(faction.pending_states()),
+            recovering_states: owned(faction.recovering_states()),
+            happiness: faction.happiness().to_string(),
+            is_player: faction.is_player(),
+        }
+    }
+}
+
+/// Influence as a percentage, like the filters take it, to the precision the dump gives it in
+fn percent(influence: f64) -> f64 {
+    (influence * 1_000_000.0).round() / 10_000.0
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct StationSummary {
+    name: String,
+    station_type: Option<String>,
+    distance_to_arrival: Option<f64>,
+    allegiance: String,
+    government: String,
+    economy: String,
+    has_market: bool,
+    has_shipyard: bool,
+    has_outfitting: bool,
+    other_services: Vec<String>,
+}
+
+impl StationSummary {
+    fn from_station(station: &dyn Station) -> Self {
+        StationSummary {
+            name: station.name().to_string(),
+            station_type: station.station_type().map(String::from),
+            distance_to_arrival: station.distance_to_arrival(),
+            allegiance: station.allegiance().to_string(),
+            government: station.government().to_string(),
+            economy: station.economy().to_string(),
+            has_market: station.has_market(),
+            has_shipyard: station.has_shipyard(),
+            has_outfitting: station.has_outfitting(),
+            other_services: station
+                .other_services()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct SystemSummary<'a> {
+    name: &'a str,
+    id64: Option<u64>,
+    x: f64,
+    y: f64,
+    z: f64,
+    population: u128,
+    allegiance: &'a str,
+    government: &'a str,
+    controlling_faction: &'a str,
+    economy: &'a str,
+    security: &'a str,
+    state: &'a str,
+    stations: usize,
+    docks: usize,
+    large_docks: usize,
+    starports: usize,
+    fleet_carriers: usize,
+    factions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minor_factions: Option<Vec<FactionSummary>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    station_details: Option<Vec<StationSummary>>,
+}
+
+/// The station types each count in a summary is made of, built once rather than for every system
+struct CountedTypes {
+    docks: HashSet<StationType>,
+    large_docks: HashSet<StationType>,
+    starports: HashSet<StationType>,
+    fleet_carriers: HashSet<StationType>,
+}
+
+impl CountedTypes {
+    fn new() -> Self {
+        CountedTypes {
+            docks: dock_types(),
+            large_docks: large_dock_types(),
+            starports: starport_types(),
+            fleet_carriers: fleet_carrier_types(),
+        }
+    }
+}
+
+impl<'a> SystemSummary<'a> {
+    fn from_system<'b, T: System<'b>>(
+        system: &'a T,
+        counted: &CountedTypes,
+        distance_from: Option<Coords>,
+        detailed: bool,
+    ) -> Self {
+        let coordinates = system.coordinates();
+        let stations = system.stations();
+        let factions = system.factions();
+        SystemSummary {
+            name: system.name(),
+            id64: system.id64(),
+            x: coordinates.x,
+            y: coordinates.y,
+            z: coordinates.z,
+            population: system.population(),
+            allegiance: system.allegiance(),
+            government: system.government(),
+            controlling_faction: system
+                .controlling_faction()
+                .map_or("", |controlling_faction| controlling_faction.name()),
+            economy: system.economy(),
+            security: system.security(),
+            state: system.state(),
+            stations: stations.len(),
+            docks: count_station_types(&stations, &counted.docks),
+            large_docks: count_station_types(&stations, &counted.large_docks),
+            starports: count_station_types(&stations, &counted.starports),
+            fleet_carriers: count_station_types(&stations, &counted.fleet_carriers),
+            factions: factions.len(),
+            distance: distance_from.map(|reference| distance(&reference, &coordinates)),
+            minor_factions: detailed.then(|| {
+                factions
+                    .iter()
+                    .map(|faction| FactionSummary::from_faction(faction.as_ref()))
+                    .collect()
+            }),
+            station_details: detailed.then(|| {
+                stations
+                    .iter()
+                    .map(|station| StationSummary::from_station(station.as_ref()))
+                    .collect()
+            }),
+        }
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec![
+            self.name.to_string(),
+            self.id64.map(|id64| id64.to_string()).unwrap_or_default(),
+            self.x.to_string(),
+            self.y.to_string(),
+            self.z.to_string(),
+            self.population.to_string(),
+            self.allegiance.to_string(),
+            self.government.to_string(),
+            self.controlling_faction.to_string(),
+            self.economy.to_string(),
+            self.security.to_string(),
+            self.state.to_string(),
+            self.stations.to_string(),
+            self.docks.to_string(),
+            self.large_docks.to_string(),
+            self.starports.to_string(),
+            self.fleet_carriers.to_string(),
+            self.factions.to_string(),
+        ]
+        .into_iter()
+        .chain(self.distance.map(|distance| format!("{distance:.2}")))
+        .collect()
+    }
+}
+
+const FIELDS: [&str; 18] = [
+    "name",
+    "id64",
+    "x",
+    "y",
+    "z",
+    "population",
+    "allegiance",
+    "government",
+    "controlling_faction",
+    "economy",
+    "security",
+    "state",
+    "stations",
+    "docks",
+    "large_docks",
+    "starports",
+    "fleet_carriers",
+    "factions",
+];
+
+const TABLE_HEADINGS: [&str; 18] = [
+    "Name",
+    "ID64",
+    "X",
+    "Y",
+    "Z",
+    "Population",
+    "Allegiance",
+    "Government",
+    "Controlling Faction",
+    "Economy",
+    "Security",
+    "State",
+    "Stations",
+    "Docks",
+    "Large Docks",
+    "Starports",
+    "Fleet Carriers",
+    "Factions",
+];
+
+/// Display the systems, including their distance in light years from a reference if one is given,
+/// and the details of each minor faction and station in the formats that can nest them
+pub fn display<'a, T: System<'a>, W: Write>(
+    format: Format,
+    systems: &[T],
+    distance_from: Option<Coords>,
+    mut writer: W,
+) -> Result<(), Error> {
+    let counted = CountedTypes::new();
+    let summaries = systems
+        .iter()
+        .map(|system| {
+            SystemSummary::from_system(
+                system,
+                &counted,
+                distance_from,
+                matches!(format, Format::Json | Format::Ndjson),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        Format::Names => {
+            for summary in summaries {
+                match summary.distance {
+                    Some(distance) => writeln!(writer, "{} ({:.2} ly)", summary.name, distance)?,
+                    None => writeln!(writer, "{}", summary.name)?,
+                }
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, &summaries)?;
+            writeln!(writer)?;
+        }
+        Format::Ndjson => {
+            for summary in summaries {
+                serde_json::to_writer(&mut writer, &summary)?;
+                writeln!(writer)?;
+            }
+        }
+        Format::Csv => {
+            let mut csv = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(writer);
+            csv.write_record(FIELDS.iter().chain(distance_from.map(|_| &"distance")))?;
+            for summary in summaries {
+                csv.serialize(summary)?;
+            }
+            csv.flush()?;
+        }
+        Format::Table => display_table(
+            &TABLE_HEADINGS
+                .iter()
+                .copied()
+                .chain(distance_from.map(|_| "Distance (ly)"))
+                .collect::<Vec<_>>(),
+            &summaries
+                .iter()
+                .map(SystemSummary::columns)
+                .collect::<Vec<_>>(),
+            writer,
+        )?,
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct StationRow<'a> {
+    system: &'a str,
+    name: String,
+    station_type: Option<String>,
+    pad: Option<&'static str>,
+    distance_to_arrival: Option<f64>,
+    services: Vec<String>,
+}
+
+impl<'a> StationRow<'a> {
+    fn from_station(system: &'a str, station: &dyn Station) -> Self {
+        StationRow {
+            system,
+            name: station.name().to_string(),
+            station_type: station.station_type().map(String::from),
+            pad: station
+                .station_type()
+                .and_then(|station_type| StationType::from(station_type).details())
+                .map(|details| details.pad.name()),
+            distance_to_arrival: station.distance_to_arrival(),
+            services: services(station).into_iter().map(String::from).collect(),
+        }
+    }
+
+    fn columns(&self) -> Vec<String> {
+        vec![
+            self.system.to_string(),
+            self.name.clone(),
+            self.station_type.clone().unwrap_or_default(),
+            self.pad.unwrap_or_default().to_string(),
+            self.distance_to_arrival
+                .map(|distance| distance.to_string())
+                .unwrap_or_default(),
+            self.services.join(", "),
+        ]
+    }
+}
+
+const STATION_FIELDS: [&str; 6] = [
+    "system",
+    "name",
+    "station_type",
+    "pad",
+    "distance_to_arrival",
+    "services",
+];
+
+const STATION_TABLE_HEADINGS: [&str; 6] = [
+    "System",
+    "Name",
+    "Type",
+    "Pad",
+    "Distance To Arrival (ls)",
+    "Services",
+];
+
+/// Display a row for each station in the systems that passes the station filters, rather than one
+/// for each system
+pub fn display_stations<'a, T: System<'a>, W: Write>(
+    format: Format,
+    systems: &[T],
+    station_filters: &[StationFilter<'_>],
+    mut writer: W,
+) -> Result<(), Error> {
+    let rows = systems
+        .iter()
+        .flat_map(|system| {
+            system
+                .stations()
+                .into_iter()
+                .filter(|station| suitable_station(station_filters, station.as_ref()))
+                .map(|station| StationRow::from_station(system.name(), station.as_ref()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        Format::Names => {
+            for row in rows {
+                writeln!(writer, "{} ({})", row.name, row.system)?;
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, &rows)?;
+            writeln!(writer)?;
+        }
+        Format::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, &row)?;
+                writeln!(writer)?;
+            }
+        }
+        Format::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            csv.write_record(STATION_FIELDS)?;
+            for row in rows {
+                csv.write_record(row.columns())?;
+            }
+            csv.flush()?;
+        }
+        Format::Table => display_table(
+            &STATION_TABLE_HEADINGS,
+            &rows.iter().map(StationRow::columns).collect::<Vec<_>>(),
+            writer,
+        )?,
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct FactionTotals {
+    name: String,
+    systems: u32,
+    controlled: u32,
+    average_influence: f64,
+    allegiance: String,
+    government: String,
+    is_player: bool,
+}
+
+impl FactionTotals {
+    fn columns(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.systems.to_string(),
+            self.controlled.to_string(),
+            format!("{:.1}", self.average_influence),
+            self.allegiance.clone(),
+            self.government.clone(),
+            self.is_player.to_string(),
+        ]
+    }
+}
+
+/// Every minor faction present in the systems, sorted by name, with how many of the systems it's
+/// in and controls
+fn faction_totals<'a, T: System<'a>>(systems: &[T]) -> Vec<FactionTotals> {
+    let mut totals: BTreeMap<String, (FactionTotals, f64)> = BTreeMap::new();
+    for system in systems {
+        let controlling_faction = system
+            .controlling_faction()
+            .map(|controlling_faction| controlling_faction.name().to_string());
+        for faction in system.factions() {
+            let (total, influence) =
+                totals.entry(faction.name().to_string()).or_insert_with(|| {
+                    (
+                        FactionTotals {
+                            name: faction.name().to_string(),
+                            systems: 0,
+                            controlled: 0,
+                            average_influence: 0.0,
+                            allegiance: faction.allegiance().to_string(),
+                            government: faction.government().to_string(),
+                            is_player: faction.is_player(),
+                        },
+                        0.0,
+                    )
+                });
+            total.systems += 1;
+            if controlling_faction.as_deref() == Some(faction.name()) {
+                total.controlled += 1;
+            }
+            *influence += faction.influence();
+        }
+    }
+
+    totals
+        .into_values()
+        .map(|(mut total, influence)| {
+            total.average_influence = percent(influence / f64::from(total.systems));
+            total
+        })
+        .collect()
+}
+
+const FACTION_FIELDS: [&str; 7] = [
+    "name",
+    "systems",
+    "controlled",
+    "average_influence",
+    "allegiance",
+    "government",
+    "is_player",
+];
+
+const FACTION_TABLE_HEADINGS: [&str; 7] = [
+    "Name",
+    "Systems",
+    "Controlled",
+    "Average Influence (%)",
+    "Allegiance",
+    "Government",
+    "Player",
+];
+
+/// Display the minor factions present in the systems rather than the systems themselves
+pub fn display_factions<'a, T: System<'a>, W: Write>(
+    format: Format,
+    systems: &[T],
+    mut writer: W,
+) -> Result<(), Error> {
+    let totals = faction_totals(systems);
+
+    match format {
+        Format::Names => {
+            for total in totals {
+                writeln!(writer, "{}", total.name)?;
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, &totals)?;
+            writeln!(writer)?;
+        }
+        Format::Ndjson => {
+            for total in totals {
+                serde_json::to_writer(&mut writer, &total)?;
+                writeln!(writer)?;
+            }
+        }
+        Format::Csv => {
+            let mut csv = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(writer);
+            csv.write_record(FACTION_FIELDS)?;
+            for total in totals {
+                csv.serialize(total)?;
+            }
+            csv.flush()?;
+        }
+        Format::Table => display_table(
+            &FACTION_TABLE_HEADINGS,
+            &totals
+                .iter()
+                .map(FactionTotals::columns)
+                .collect::<Vec<_>>(),
+            writer,
+        )?,
+    }
+
+    Ok(())
+}
+
+fn display_table<W: Write>(
+    headings: &[&str],
+    rows: &[Vec<String>],
+    mut writer: W,
+) -> Result<(), Error> {
+    let widths = headings
+        .iter()
+        .enumerate()
+        .map(|(column, heading)| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .chain(std::iter::once(heading.chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let headings = headings
+        .iter()
+        .map(|heading| (*heading).to_string())
+        .collect::<Vec<_>>();
+    for row in std::iter::once(&headings).chain(rows) {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| format!("{column:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(writer, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+}
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("failed to write output: {0:?}")]
+    Write(#[from] std::io::Error),
+    #[error("failed to write json: {0:?}")]
+    Json(#[from] serde_json::Error),
+    #[error("failed to write csv: {0:?}")]
+    Csv(#[from] csv::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Coords;
+    use crate::output::{display, display_factions, display_stations, Format};
+    use crate::{domain, stub};
+
+    fn systems() -> Vec<stub::System> {
+        vec![
+            stub::System {
+                name: String::from("Sanos"),
+                coords: domain::Coords {
+                    x: 73.875_f64,
+                    y: -3.5625_f64,
+                    z: -52.625_f64,
+                },
+                population: 6_149_044_545,
+                factions: vec![
+                    stub::Faction {
+                        name: String::from("Federal Liberal Command"),
+                        influence: 0.523_904,
+                        is_player: true,
+                        ..stub::Faction::default()
+                    },
+                    stub::Faction {
+                        name: String::from("Independents of Sanos"),
+                        influence: 0.167_331,
+                        ..stub::Faction::default()
+                    },
+                ],
+                stations: vec![
+                    stub::Station {
+                        name: String::from("K9T-38W"),
+                        station_type: Some(String::from("Fleet Carrier")),
+                        distance_to_arrival: Some(296.807_847),
+                        has_market: true,
+                        other_services: vec![String::from("Refuel")],
+                        ..stub::Station::default()
+                    },
+                    stub::Station {
+                        name: String::from("Apgar Terminal"),
+                        station_type: Some(String::from("Orbis Starport")),
+                        distance_to_arrival: Some(296.864_456),
+                        has_shipyard: true,
+                        ..stub::Station::default()
+                    },
+                ],
+                allegiance: String::from("Federation"),
+                government: String::from("Corporate"),
+                controlling_faction: Some(stub::ControllingFaction {
+                    name: String::from("Federal Liberal Command"),
+                    allegiance: String::from("Federation"),
+                    government: String::from("Corporate"),
+                    is_player: true,
+                }),
+                id64: Some(3_932_277_445_322),
+                economy: String::from("Agriculture"),
+                security: String::from("High"),
+                state: String::from("None"),
+            },
+            stub::System {
+                name: String::from("Sol"),
+                coords: domain::Coords {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                population: 0,
+                factions: vec![],
+                stations: vec![],
+                allegiance: String::new(),
+                government: String::new(),
+                controlling_faction: None,
+                id64: None,
+                economy: String::new(),
+                security: String::new(),
+                state: String::new(),
+            },
+        ]
+    }
+
+    fn display_with_distance_to_string(format: Format) -> String {
+        let mut output = vec![];
+        display(
+            format,
+            &systems(),
+            Some(Coords {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            }),
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn display_to_string(format: Format) -> String {
+        let mut output = vec![];
+        display(format, &systems(), None, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn csv_without_systems_still_has_a_header() {
+        let mut output = vec![];
+        display::<stub::System, _>(Format::Csv, &[], None, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "name,id64,x,y,z,population,allegiance,government,controlling_faction,economy,security,state,stations,docks,large_docks,starports,fleet_carriers,factions\n"
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(display_to_string(Format::Names), "Sanos\nSol\n");
+    }
+
+    #[test]
+    fn ndjson() {
+        assert_eq!(
+            display_to_string(Format::Ndjson),
+            concat!(
+                r#"{"name":"Sanos","id64":3932277445322,"x":73.875,"y":-3.5625,"z":-52.625,"population":6149044545,"allegiance":"Federation","government":"Corporate","controlling_faction":"Federal Liberal Command","economy":"Agriculture","security":"High","state":"None","stations":2,"docks":1,"large_docks":1,"starports":1,"fleet_carriers":1,"factions":2,"minor_factions":[{"name":"Federal Liberal Command","allegiance":"","government":"","influence":52.3904,"state":"","active_states":[],"pending_states":[],"recovering_states":[],"happiness":"","is_player":true},{"name":"Independents of Sanos","allegiance":"","government":"","influence":16.7331,"state":"","active_states":[],"pending_states":[],"recovering_states":[],"happiness":"","is_player":false}],"station_details":[{"name":"K9T-38W","station_type":"Fleet Carrier","distance_to_arrival":296.807847,"allegiance":"","government":"","economy":"","has_market":true,"has_shipyard":false,"has_outfitting":false,"other_services":["Refuel"]},{"name":"Apgar Terminal","station_type":"Orbis Starport","distance_to_arrival":296.864456,"allegiance":"","government":"","economy":"","has_market":false,"has_shipyard":true,"has_outfitting":false,"other_services":[]}]}"#,
+                "\n",
+                r#"{"name":"Sol","id64":null,"x":0.0,"y":0.0,"z":0.0,"population":0,"allegiance":"","government":"","controlling_faction":"","economy":"","security":"","state":"","stations":0,"docks":0,"large_docks":0,"starports":0,"fleet_carriers":0,"factions":0,"minor_factions":[],"station_details":[]}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn json() {
+        let output: serde_json::Value =
+            serde_json::from_str(&display_to_string(Format::Json)).unwrap();
+
+        assert_eq!(output[0]["name"], "Sanos");
+        assert_eq!(output[0]["population"], 6_149_044_545_u64);
+        assert_eq!(
+            output[0]["minor_factions"][0]["name"],
+            "Federal Liberal Command"
+        );
+        assert_eq!(output[0]["minor_factions"][0]["is_player"], true);
+        assert_eq!(output[0]["station_details"][1]["name"], "Apgar Terminal");
+        assert_eq!(output[0]["station_details"][1]["has_shipyard"], true);
+        assert_eq!(output[1]["name"], "Sol");
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            display_to_string(Format::Csv),
+            concat!(
+                "name,id64,x,y,z,population,allegiance,government,controlling_faction,economy,security,state,stations,docks,large_docks,starports,fleet_carriers,factions\n",
+                "Sanos,3932277445322,73.875,-3.5625,-52.625,6149044545,Federation,Corporate,Federal Liberal Command,Agriculture,High,None,2,1,1,1,1,2\n",
+                "Sol,,0.0,0.0,0.0,0,,,,,,,0,0,0,0,0,0\n",
+            )
+        );
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            display_to_string(Format::Table),
+            concat!(
+                "Name   ID64           X       Y        Z        Population  Allegiance  Government  Controlling Faction      Economy      Security  State  Stations  Docks  Large Docks  Starports  Fleet Carriers  Factions\n",
+                "Sanos  3932277445322  73.875  -3.5625  -52.625  6149044545  Federation  Corporate   Federal Liberal Command  Agriculture  High      None   2         1      1            1          1               2\n",
+                "Sol                   0       0        0        0                                                                                          0         0      0            0          0               0\n",
+            )
+        );
+    }
+
+    #[test]
+    fn names_with_distance() {
+        assert_eq!(
+            display_with_distance_to_string(Format::Names),
+            "Sanos (90.77 ly)\nSol (0.00 ly)\n"
+        );
+    }
+
+    #[test]
+    fn csv_with_distance() {
+        assert_eq!(
+            display_with_distance_to_string(Format::Csv)
+                .lines()
+                .map(|line| line.rsplit(',').next().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["distance", "90.7722295432364", "0.0"]
+        );
+    }
+
+    #[test]
+    fn table_with_distance() {
+        assert_eq!(
+            display_with_distance_to_string(Format::Table)
+                .lines()
+                .map(|line| line.rsplit("  ").next().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["Distance (ly)", "90.77", "0.00"]
+        );
+    }
+
+    fn display_stations_to_string(
+        format: Format,
+        station_filters: &[domain::StationFilter<'_>],
+    ) -> String {
+        let mut output = vec![];
+        display_stations(format, &systems(), station_filters, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn stations_names() {
+        assert_eq!(
+            display_stations_to_string(Format::Names, &[]),
+            "K9T-38W (Sanos)\nApgar Terminal (Sanos)\n"
+        );
+    }
+
+    #[test]
+    fn stations_ndjson() {
+        assert_eq!(
+            display_stations_to_string(Format::Ndjson, &[]),
+            concat!(
+                r#"{"system":"Sanos","name":"K9T-38W","station_type":"Fleet Carrier","pad":"large","distance_to_arrival":296.807847,"services":["Market","Refuel"]}"#,
+                "\n",
+                r#"{"system":"Sanos","name":"Apgar Terminal","station_type":"Orbis Starport","pad":"large","distance_to_arrival":296.864456,"services":["Shipyard"]}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn stations_csv() {
+        assert_eq!(
+            display_stations_to_string(Format::Csv, &[]),
+            concat!(
+                "system,name,station_type,pad,distance_to_arrival,services\n",
+                "Sanos,K9T-38W,Fleet Carrier,large,296.807847,\"Market, Refuel\"\n",
+                "Sanos,Apgar Terminal,Orbis Starport,large,296.864456,Shipyard\n",
+            )
+        );
+    }
+
+    #[test]
+    fn stations_table() {
+        assert_eq!(
+            display_stations_to_string(Format::Table, &[]),
+            concat!(
+                "System  Name            Type            Pad    Distance To Arrival (ls)  Services\n",
+                "Sanos   K9T-38W         Fleet Carrier   large  296.807847                Market, Refuel\n",
+                "Sanos   Apgar Terminal  Orbis Starport  large  296.864456                Shipyard\n",
+            )
+        );
+    }
+
+    #[test]
+    fn stations_filtered() {
+        assert_eq!(
+            display_stations_to_string(Format::Names, &[domain::station_with_service("Shipyard")]),
+            "Apgar Terminal (Sanos)\n"
+        );
+    }
+
+    fn display_factions_to_string(format: Format) -> String {
+        let mut systems = systems();
+        systems[1].factions = vec![stub::Faction {
+            name: String::from("Independents of Sanos"),
+            influence: 0.5,
+            ..stub::Faction::default()
+        }];
+        systems[1].controlling_faction = Some(stub::ControllingFaction {
+            name: String::from("Independents of Sanos"),
+            allegiance: String::new(),
+            government: String::new(),
+            is_player: false,
+        });
+
+        let mut output = vec![];
+        display_factions(format, &systems, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn factions_names() {
+        assert_eq!(
+            display_factions_to_string(Format::Names),
+            "Federal Liberal Command\nIndependents of Sanos\n"
+        );
+    }
+
+    #[test]
+    fn factions_ndjson() {
+        assert_eq!(
+            display_factions_to_string(Format::Ndjson),
+            concat!(
+                r#"{"name":"Federal Liberal Command","systems":1,"controlled":1,"average_influence":52.3904,"allegiance":"","government":"","is_player":true}"#,
+                "\n",
+                r#"{"name":"Independents of Sanos","systems":2,"controlled":1,"average_influence":33.3666,"allegiance":"","government":"","is_player":false}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn factions_csv() {
+        assert_eq!(
+            display_factions_to_string(Format::Csv),
+            concat!(
+                "name,systems,controlled,average_influence,allegiance,government,is_player\n",
+                "Federal Liberal Command,1,1,52.3904,,,true\n",
+                "Independents of Sanos,2,1,33.3666,,,false\n",
+            )
+        );
+    }
+
+    #[test]
+    fn factions_csv_without_systems_still_has_a_header() {
+        let mut output = vec![];
+        display_factions::<stub::System, _>(Format::Csv, &[], &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "name,systems,controlled,average_influence,allegiance,government,is_player\n"
+        );
+    }
+
+    #[test]
+    fn factions_table() {
+        assert_eq!(
+            display_factions_to_string(Format::Table),
+            concat!(
+                "Name                     Systems  Controlled  Average Influence (%)  Allegiance  Government  Player\n",
+                "Federal Liberal Command  1        1           52.4                                           true\n",
+                "Independents of Sanos    2        1           33.4                                           false\n",
+            )
+        );
+    }
+}
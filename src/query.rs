@@ -0,0 +1,672 @@
+use std::convert::TryFrom;
+
+use thiserror::Error as ThisError;
+
+use crate::domain;
+use crate::domain::{
+    allegiance, and, controlled_by, controlling_faction_allegiance, controlling_faction_government,
+    economy, exclude_permit_locked, exclude_player_controlled, exclude_player_faction,
+    exclude_rare_commodity, faction_in_state, faction_present, government,
+    max_distance_from_reference, max_distance_from_sol, max_number_of_factions,
+    min_distance_from_reference, min_distance_from_sol, min_docks, min_fleet_carriers,
+    min_large_docks, min_population, min_starports, not, or, require_service, security,
+    system_state, Coords, StateStage, SystemFilter,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Identifier(&'a str),
+    Text(&'a str),
+    Number(&'a str),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Operator {
+    const fn symbol(self) -> &'static str {
+        match self {
+            Operator::Equal => "=",
+            Operator::NotEqual => "!=",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<(usize, Token<'_>)>, Error> {
+    let mut tokens = vec![];
+    let mut chars = query.char_indices().peekable();
+
+    while let Some((position, character)) = chars.next() {
+        let token = match character {
+            _ if character.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' | '\'' => {
+                let end = chars
+                    .find(|(_, closing)| *closing == character)
+                    .map(|(end, _)| end)
+                    .ok_or(Error::UnterminatedText(position))?;
+                Token::Text(&query[position + 1..end])
+            }
+            '=' => Token::Operator(Operator::Equal),
+            '!' if chars.next_if(|(_, next)| *next == '=').is_some() => {
+                Token::Operator(Operator::NotEqual)
+            }
+            '<' if chars.next_if(|(_, next)| *next == '=').is_some() => {
+                Token::Operator(Operator::LessOrEqual)
+            }
+            '<' => Token::Operator(Operator::Less),
+            '>' if chars.next_if(|(_, next)| *next == '=').is_some() => {
+                Token::Operator(Operator::GreaterOrEqual)
+            }
+            '>' => Token::Operator(Operator::Greater),
+            _ if character.is_ascii_digit() || character == '-' || character == '.' => {
+                let end = take_while(&mut chars, query.len(), |next| {
+                    next.is_ascii_digit() || next == '.' || next == '_'
+                });
+                Token::Number(&query[position..end])
+            }
+            _ if character.is_alphabetic() || character == '_' => {
+                let end = take_while(&mut chars, query.len(), |next| {
+                    next.is_alphanumeric() || next == '_'
+                });
+                Token::Identifier(&query[position..end])
+            }
+            _ => return Err(Error::UnexpectedCharacter(character, position)),
+        };
+
+        tokens.push((position, token));
+    }
+
+    Ok(tokens)
+}
+
+fn take_while<I: Iterator<Item = (usize, char)>>(
+    chars: &mut std::iter::Peekable<I>,
+    end_of_query: usize,
+    predicate: impl Fn(char) -> bool,
+) -> usize {
+    while chars.next_if(|(_, next)| predicate(*next)).is_some() {}
+    chars.peek().map_or(end_of_query, |(end, _)| *end)
+}
+
+/// How deeply brackets and `not` can nest, so a query can't recurse until the stack overflows
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token<'a>)>,
+    position: usize,
+    depth: usize,
+    reference: Option<Coords>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).map(|(_, token)| *token)
+    }
+
+    fn next(&mut self, expected: &'static str) -> Result<Token<'a>, Error> {
+        let token = self.peek().ok_or(Error::UnexpectedEnd(expected))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn unexpected(&self, expected: &'static str) -> Error {
+        self.tokens
+            .get(self.position.saturating_sub(1))
+            .map_or(Error::UnexpectedEnd(expected), |(position, _)| {
+                Error::UnexpectedToken(*position, expected)
+            })
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(
+            self.peek(),
+            Some(Token::Identifier(identifier)) if identifier.eq_ignore_ascii_case(keyword)
+        );
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expression(&mut self) -> Result<SystemFilter<'a>, Error> {
+        let mut filters = vec![self.conjunction()?];
+        while self.keyword("or") {
+            filters.push(self.conjunction()?);
+        }
+
+        Ok(if filters.len() == 1 {
+            filters.remove(0)
+        } else {
+            or(filters)
+        })
+    }
+
+    fn conjunction(&mut self) -> Result<SystemFilter<'a>, Error> {
+        let mut filters = vec![self.unary()?];
+        while self.keyword("and") {
+            filters.push(self.unary()?);
+        }
+
+        Ok(if filters.len() == 1 {
+            filters.remove(0)
+        } else {
+            and(filters)
+        })
+    }
+
+    fn unary(&mut self) -> Result<SystemFilter<'a>, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::TooDeep(MAX_DEPTH));
+        }
+
+        self.depth += 1;
+        let filter = self.nested();
+        self.depth -= 1;
+        filter
+    }
+
+    fn nested(&mut self) -> Result<SystemFilter<'a>, Error> {
+        if self.keyword("not") {
+            return Ok(not(self.unary()?));
+        }
+
+        match self.next("a filter")? {
+            Token::Open => {
+                let filter = self.expression()?;
+                match self.next("')'")? {
+                    Token::Close => Ok(filter),
+                    _ => Err(self.unexpected("')'")),
+                }
+            }
+            Token::Identifier(field) => self.predicate(field),
+            _ => Err(self.unexpected("a filter")),
+        }
+    }
+
+    fn predicate(&mut self, field: &'a str) -> Result<SystemFilter<'a>, Error> {
+        match field {
+            "player_faction" => Ok(not(exclude_player_faction())),
+            "player_controlled" => Ok(not(exclude_player_controlled())),
+            "permit_locked" => Ok(not(exclude_permit_locked())),
+            "rare_commodity" => Ok(not(exclude_rare_commodity())),
+            "allegiance" => self.text(field, |value| allegiance(value.into())),
+            "government" => self.text(field, |value| government(value.into())),
+            "economy" => self.text(field, |value| economy(value.into())),
+            "security" => self.text(field, |value| security(value.into())),
+            "system_state" => self.text(field, system_state),
+            "faction" => self.text(field, faction_present),
+            "controlling_faction" => self.text(field, controlled_by),
+            "controlling_allegiance" => {
+                self.text(field, |value| controlling_faction_allegiance(value.into()))
+            }
+            "controlling_government" => {
+                self.text(field, |value| controlling_faction_government(value.into()))
+            }
+            "pending_state" => {
+                self.text(field, |state| faction_in_state(state, StateStage::Pending))
+            }
+            "active_state" => self.text(field, |state| faction_in_state(state, StateStage::Active)),
+            "recovering_state" => self.text(field, |state| {
+                faction_in_state(state, StateStage::Recovering)
+            }),
+            "service" => self.text(field, require_service),
+            "population" => self.count(|count| Some(min_population(count))),
+            "docks" => self.count(|count| usize::try_from(count).ok().map(min_docks)),
+            "large_docks" => self.count(|count| usize::try_from(count).ok().map(min_large_docks)),
+            "starports" => self.count(|count| usize::try_from(count).ok().map(min_starports)),
+            "fleet_carriers" => {
+                self.count(|count| usize::try_from(count).ok().map(min_fleet_carriers))
+            }
+            "factions" => self.count(|count| match count {
+                0 => Some(and(vec![])),
+                _ => usize::try_from(count - 1)
+                    .ok()
+                    .map(|count| not(max_number_of_factions(count))),
+            }),
+            "distance_from_sol" => {
+                self.distance(field, max_distance_from_sol, min_distance_from_sol)
+            }
+            "distance_from_reference" => {
+                let reference = self.reference.ok_or(Error::MissingReference)?;
+                self.distance(
+                    field,
+                    |distance| max_distance_from_reference(reference, distance),
+                    |distance| min_distance_from_reference(reference, distance),
+                )
+            }
+            _ => Err(Error::UnknownField(field.into())),
+        }
+    }
+
+    fn operator(&mut self) -> Result<Operator, Error> {
+        match self.next("an operator")? {
+            Token::Operator(operator) => Ok(operator),
+            _ => Err(self.unexpected("an operator")),
+        }
+    }
+
+    fn number(&mut self) -> Result<String, Error> {
+        let Token::Number(value) = self.next("a number")? else {
+            return Err(self.unexpected("a number"));
+        };
+        Ok(value.replace('_', ""))
+    }
+
+    fn unsupported(field: &str, operator: Operator) -> Error {
+        Error::UnsupportedOperator(field.into(), operator.symbol())
+    }
+
+    fn text(
+        &mut self,
+        field: &str,
+        filter: fn(&'a str) -> SystemFilter<'a>,
+    ) -> Result<SystemFilter<'a>, Error> {
+        let operator = self.operator()?;
+        let Token::Text(value) = self.next("a quoted value")? else {
+            return Err(self.unexpected("a quoted value"));
+        };
+
+        match operator {
+            Operator::Equal => Ok(filter(value)),
+            Operator::NotEqual => Ok(not(filter(value))),
+            _ => Err(Self::unsupported(field, operator)),
+        }
+    }
+
+    /// Whole number comparisons are all built from an "at least" filter, which is `None` when the
+    /// count is too large for any system to reach
+    fn count(
+        &mut self,
+        at_least: impl Fn(u128) -> Option<SystemFilter<'a>>,
+    ) -> Result<SystemFilter<'a>, Error> {
+        let operator = self.operator()?;
+        let value = self.number()?;
+        let count: u128 = value
+            .parse()
+            .map_err(|_| Error::InvalidNumber(value.clone()))?;
+
+        let at_least_or_never =
+            |count: Option<u128>| count.and_then(&at_least).unwrap_or_else(|| or(vec![]));
+        let more_than = at_least_or_never(count.checked_add(1));
+        let at_least = at_least_or_never(Some(count));
+
+        Ok(match operator {
+            Operator::GreaterOrEqual => at_least,
+            Operator::Greater => more_than,
+            Operator::Less => not(at_least),
+            Operator::LessOrEqual => not(more_than),
+            Operator::Equal => and(vec![at_least, not(more_than)]),
+            Operator::NotEqual => not(and(vec![at_least, not(more_than)])),
+        })
+    }
+
+    /// Inclusive comparisons are the filters themselves, the strict ones are the opposite of the
+    /// other filter, so a system exactly at the distance is only kept by `<=` and `>=`
+    fn distance(
+        &mut self,
+        field: &str,
+        at_most: impl Fn(f64) -> SystemFilter<'a>,
+        at_least: impl Fn(f64) -> SystemFilter<'a>,
+    ) -> Result<SystemFilter<'a>, Error> {
+        let operator = self.operator()?;
+        let value = self.number()?;
+        let distance: f64 = value
+            .parse()
+            .map_err(|_| Error::InvalidNumber(value.clone()))?;
+
+        match operator {
+            Operator::LessOrEqual => Ok(at_most(distance)),
+            Operator::Less => Ok(not(at_least(distance))),
+            Operator::GreaterOrEqual => Ok(at_least(distance)),
+            Operator::Greater => Ok(not(at_most(distance))),
+            _ => Err(Self::unsupported(field, operator)),
+        }
+    }
+}
+
+pub fn parse(query: &str, reference: Option<Coords>) -> Result<domain::SystemFilter<'_>, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        position: 0,
+        depth: 0,
+        reference,
+    };
+
+    let filter = parser.expression()?;
+    if parser.peek().is_some() {
+        parser.position += 1;
+        return Err(parser.unexpected("'and', 'or' or the end of the query"));
+    }
+
+    Ok(filter)
+}
+
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("unexpected end of query, expected {0}")]
+    UnexpectedEnd(&'static str),
+    #[error("unexpected token at position {0}, expected {1}")]
+    UnexpectedToken(usize, &'static str),
+    #[error("unexpected character {0:?} at position {1}")]
+    UnexpectedCharacter(char, usize),
+    #[error("unterminated quoted value starting at position {0}")]
+    UnterminatedText(usize),
+    #[error("unknown field: {0}")]
+    UnknownField(String),
+    #[error("{0} can't be compared with {1}")]
+    UnsupportedOperator(String, &'static str),
+    #[error("invalid number: {0}")]
+    InvalidNumber(String),
+    #[error("distance_from_reference needs a reference system")]
+    MissingReference,
+    #[error("brackets and not can't be nested more than {0} deep")]
+    TooDeep(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain;
+    use crate::domain::{
+        allegiance, and, controlled_by, controlling_faction_allegiance,
+        controlling_faction_government, exclude_permit_locked, exclude_player_controlled,
+        exclude_player_faction, faction_in_state, faction_present, government,
+        max_distance_from_reference, max_distance_from_sol, max_number_of_factions,
+        min_distance_from_reference, min_distance_from_sol, min_docks, min_fleet_carriers,
+        min_population, not, or, Allegiance, Coords, Economy, Government, Security, StateStage,
+    };
+    use crate::filter::filter;
+    use crate::query::{parse, Error};
+    use crate::stub;
+
+    #[test]
+    fn single_predicate() {
+        assert_eq!(
+            parse(r#"allegiance = "Federation""#, None).unwrap(),
+            allegiance(Allegiance::Federation)
+        );
+    }
+
+    #[test]
+    fn values_ignore_case() {
+        assert_eq!(
+            parse(r#"allegiance = "federation" and security = "LOW""#, None).unwrap(),
+            and(vec![
+                allegiance(Allegiance::Federation),
+                domain::security(Security::Low)
+            ])
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse(
+                r#"allegiance = "Federation" and government = "Democracy" or government = 'Corporate'"#,
+                None
+            )
+            .unwrap(),
+            or(vec![
+                and(vec![allegiance(Allegiance::Federation), government(Government::Democracy)]),
+                government(Government::Corporate)
+            ])
+        );
+    }
+
+    #[test]
+    fn brackets_and_not() {
+        assert_eq!(
+            parse(
+                r#"allegiance = "Federation" and (government = "Democracy" or government = "Corporate") and not player_faction"#,
+                None
+            )
+            .unwrap(),
+            and(vec![
+                allegiance(Allegiance::Federation),
+                or(vec![government(Government::Democracy), government(Government::Corporate)]),
+                exclude_player_faction()
+            ])
+        );
+    }
+
+    #[test]
+    fn not_equal() {
+        assert_eq!(
+            parse(r#"government != "Anarchy" and permit_locked"#, None).unwrap(),
+            and(vec![
+                not(government(Government::Anarchy)),
+                not(exclude_permit_locked())
+            ])
+        );
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(
+            parse("population >= 1_000 and docks > 1", None).unwrap(),
+            and(vec![min_population(1000), min_docks(2)])
+        );
+        assert_eq!(
+            parse("factions <= 7", None).unwrap(),
+            max_number_of_factions(7)
+        );
+        assert_eq!(
+            parse("population = 10", None).unwrap(),
+            and(vec![min_population(10), not(min_population(11))])
+        );
+        assert_eq!(
+            parse("fleet_carriers < 3", None).unwrap(),
+            not(min_fleet_carriers(3))
+        );
+    }
+
+    #[test]
+    fn services() {
+        assert_eq!(
+            parse(
+                r#"service = "Material Trader" or service != "Shipyard""#,
+                None
+            )
+            .unwrap(),
+            or(vec![
+                domain::require_service("Material Trader"),
+                not(domain::require_service("Shipyard"))
+            ])
+        );
+    }
+
+    #[test]
+    fn factions() {
+        assert_eq!(
+            parse(
+                r#"faction = 'Mother Gaia' and faction != "Sol Workers' Party""#,
+                None
+            )
+            .unwrap(),
+            and(vec![
+                faction_present("Mother Gaia"),
+                not(faction_present("Sol Workers' Party"))
+            ])
+        );
+    }
+
+    #[test]
+    fn faction_states() {
+        assert_eq!(
+            parse(
+                r#"active_state = "War" or pending_state = "War" and recovering_state != "Boom""#,
+                None
+            )
+            .unwrap(),
+            or(vec![
+                faction_in_state("War", StateStage::Active),
+                and(vec![
+                    faction_in_state("War", StateStage::Pending),
+                    not(faction_in_state("Boom", StateStage::Recovering))
+                ])
+            ])
+        );
+    }
+
+    #[test]
+    fn controlling_faction() {
+        assert_eq!(
+            parse(
+                r#"controlling_faction = "Mother Gaia" or not player_controlled and controlling_allegiance = "Federation" and controlling_government != "Anarchy""#,
+                None
+            )
+            .unwrap(),
+            or(vec![
+                controlled_by("Mother Gaia"),
+                and(vec![
+                    exclude_player_controlled(),
+                    controlling_faction_allegiance(Allegiance::Federation),
+                    not(controlling_faction_government(Government::Anarchy))
+                ])
+            ])
+        );
+    }
+
+    #[test]
+    fn economy_security_and_state() {
+        assert_eq!(
+            parse(
+                r#"economy = "Industrial" and security != "Low" and system_state = "Boom""#,
+                None
+            )
+            .unwrap(),
+            and(vec![
+                domain::economy(Economy::Industrial),
+                not(domain::security(Security::Low)),
+                domain::system_state("Boom")
+            ])
+        );
+    }
+
+    #[test]
+    fn distances() {
+        let reference = Coords {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        };
+
+        assert_eq!(
+            parse(
+                "distance_from_sol < 500 and distance_from_reference > 20.5",
+                Some(reference)
+            )
+            .unwrap(),
+            and(vec![
+                not(min_distance_from_sol(500.0)),
+                not(max_distance_from_reference(reference, 20.5))
+            ])
+        );
+        assert_eq!(
+            parse(
+                "distance_from_sol <= 500 and distance_from_reference >= 20.5",
+                Some(reference)
+            )
+            .unwrap(),
+            and(vec![
+                max_distance_from_sol(500.0),
+                min_distance_from_reference(reference, 20.5)
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn distances_at_the_boundary() {
+        let make_system = |name: &str, x: f64| stub::System {
+            name: String::from(name),
+            coords: Coords { x, y: 0.0, z: 0.0 },
+            population: 0,
+            factions: vec![],
+            stations: vec![],
+            allegiance: String::new(),
+            government: String::new(),
+            controlling_faction: None,
+            id64: None,
+            economy: String::new(),
+            security: String::new(),
+            state: String::new(),
+        };
+        let at_the_boundary = make_system("Alpha", 10.0);
+        let sol = make_system("Sol", 0.0);
+        let found = |query: &'static str| {
+            let systems = [at_the_boundary.clone(), sol.clone()];
+            async move {
+                filter(&[parse(query, None).unwrap()], systems)
+                    .await
+                    .into_iter()
+                    .map(|system| system.name)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(found("distance_from_sol < 10").await, vec!["Sol"]);
+        assert_eq!(found("distance_from_sol <= 10").await, vec!["Alpha", "Sol"]);
+        assert_eq!(found("distance_from_sol > 10").await, Vec::<String>::new());
+        assert_eq!(found("distance_from_sol >= 10").await, vec!["Alpha"]);
+        assert_eq!(found("distance_from_sol >= 0").await, vec!["Alpha", "Sol"]);
+    }
+
+    #[test]
+    fn distance_from_reference_needs_a_reference() {
+        assert!(parse("distance_from_reference < 10", None).is_err());
+    }
+
+    #[test]
+    fn invalid_queries() {
+        for query in [
+            "",
+            "allegiance",
+            r#"allegiance = "Federation"#,
+            r#"allegiance > "Federation""#,
+            r#"(allegiance = "Federation""#,
+            r#"allegiance = "Federation" government = "Democracy""#,
+            "population >= lots",
+            "population >= -1",
+            "colour = 'blue'",
+            "docks ~ 1",
+        ] {
+            assert!(parse(query, None).is_err(), "{} should not parse", query);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_queries() {
+        let nested = |depth: usize| {
+            format!(
+                "{}player_faction{}",
+                "(not ".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+
+        assert!(parse(&nested(100), None).is_ok());
+        assert!(matches!(
+            parse(&nested(50_000), None),
+            Err(Error::TooDeep(256))
+        ));
+        assert!(matches!(
+            parse(&"not ".repeat(50_000), None),
+            Err(Error::TooDeep(256))
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::distance::distance;
+use crate::domain::Coords;
+
+/// A k-d tree over coordinates, answering radius and nearest neighbour queries without visiting
+/// every point
+#[derive(Debug, Clone)]
+pub struct KdTree<T> {
+    points: Vec<(Coords, T)>,
+}
+
+impl<T> KdTree<T> {
+    pub fn new(mut points: Vec<(Coords, T)>) -> Self {
+        build(&mut points, 0);
+        KdTree { points }
+    }
+
+    /// Whether there are no points in the tree
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Every point no further than the radius from the centre, with its distance
+    pub fn within(&self, centre: &Coords, radius: f64) -> Vec<(&T, f64)> {
+        let mut found = vec![];
+        within(&self.points, 0, centre, radius, &mut found);
+        found
+    }
+
+    /// The closest points to the centre, nearest first
+    pub fn nearest(&self, centre: &Coords, count: usize) -> Vec<(&T, f64)> {
+        let mut candidates = BinaryHeap::with_capacity(count.min(self.points.len()));
+        if count > 0 {
+            nearest(&self.points, 0, 0, centre, count, &mut candidates);
+        }
+
+        candidates
+            .into_sorted_vec()
+            .into_iter()
+            .map(|candidate| (&self.points[candidate.index].1, candidate.distance))
+            .collect()
+    }
+}
+
+fn axis(coords: &Coords, depth: usize) -> f64 {
+    match depth % 3 {
+        0 => coords.x,
+        1 => coords.y,
+        _ => coords.z,
+    }
+}
+
+/// Arrange the points so the median on this depth's axis is in the middle of the slice, with
+/// everything before it no greater and everything after no less, then do the same for each half
+fn build<T>(points: &mut [(Coords, T)], depth: usize) {
+    if points.len() <= 1 {
+        return;
+    }
+
+    let median = points.len() / 2;
+    points.select_nth_unstable_by(median, |a, b| {
+        axis(&a.0, depth).total_cmp(&axis(&b.0, depth))
+    });
+
+    let (before, after) = points.split_at_mut(median);
+    build(before, depth + 1);
+    build(&mut after[1..], depth + 1);
+}
+
+fn within<'a, T>(
+    points: &'a [(Coords, T)],
+    depth: usize,
+    centre: &Coords,
+    radius: f64,
+    found: &mut Vec<(&'a T, f64)>,
+) {
+    if points.is_empty() {
+        return;
+    }
+
+    let median = points.len() / 2;
+    let (coords, value) = &points[median];
+    let distance_to_point = distance(centre, coords);
+    if distance_to_point <= radius {
+        found.push((value, distance_to_point));
+    }
+
+    let offset = axis(centre, depth) - axis(coords, depth);
+    let (before, after) = (&points[..median], &points[median + 1..]);
+    let (near, far) = if offset < 0.0 {
+        (before, after)
+    } else {
+        (after, before)
+    };
+
+    within(near, depth + 1, centre, radius, found);
+    if offset.abs() <= radius {
+        within(far, depth + 1, centre, radius, found);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    distance: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+fn nearest<T>(
+    points: &[(Coords, T)],
+    start: usize,
+    depth: usize,
+    centre: &Coords,
+    count: usize,
+    candidates: &mut BinaryHeap<Candidate>,
+) {
+    if points.is_empty() {
+        return;
+    }
+
+    let median = points.len() / 2;
+    let coords = &points[median].0;
+    let candidate = Candidate {
+        distance: distance(centre, coords),
+        index: start + median,
+    };
+    if candidates.len() < count {
+        candidates.push(candidate);
+    } else if candidates
+        .peek()
+        .is_some_and(|furthest| candidate < *furthest)
+    {
+        candidates.pop();
+        candidates.push(candidate);
+    }
+
+    let offset = axis(centre, depth) - axis(coords, depth);
+    let before = (&points[..median], start);
+    let after = (&points[median + 1..], start + median + 1);
+    let (near, far) = if offset < 0.0 {
+        (before, after)
+    } else {
+        (after, before)
+    };
+
+    nearest(near.0, near.1, depth + 1, centre, count, candidates);
+    if candidates.len() < count
+        || candidates
+            .peek()
+            .is_none_or(|furthest| offset.abs() <= furthest.distance)
+    {
+        nearest(far.0, far.1, depth + 1, centre, count, candidates);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distance::distance;
+    use crate::domain::Coords;
+    use crate::spatial::KdTree;
+
+    fn grid() -> Vec<(Coords, usize)> {
+        (0..1000_u32)
+            .zip(0_usize..)
+            .map(|(point, index)| {
+                (
+                    Coords {
+                        x: f64::from(point % 10) * 3.5,
+                        y: f64::from(point / 10 % 10) * -2.25,
+                        z: f64::from(point / 100) * 7.0,
+                    },
+                    index,
+                )
+            })
+            .collect()
+    }
+
+    fn brute_force(centre: &Coords, radius: f64) -> Vec<usize> {
+        let mut found = grid()
+            .into_iter()
+            .filter(|(coords, _)| distance(centre, coords) <= radius)
+            .map(|(_, index)| index)
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        found
+    }
+
+    #[test]
+    fn within_finds_the_same_points_as_checking_every_point() {
+        let tree = KdTree::new(grid());
+        let centre = Coords {
+            x: 12.0,
+            y: -10.0,
+            z: 30.0,
+        };
+
+        for radius in [0.0, 1.0, 5.0, 12.5, 100.0] {
+            let mut found = tree
+                .within(&centre, radius)
+                .into_iter()
+                .map(|(index, _)| *index)
+                .collect::<Vec<_>>();
+            found.sort_unstable();
+
+            assert_eq!(found, brute_force(&centre, radius));
+        }
+    }
+
+    #[test]
+    fn within_an_empty_tree() {
+        let tree: KdTree<()> = KdTree::new(vec![]);
+        assert!(tree
+            .within(
+                &Coords {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0
+                },
+                10.0
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn nearest_is_sorted_by_distance() {
+        let tree = KdTree::new(grid());
+        let centre = Coords {
+            x: -4.1,
+            y: 3.3,
+            z: 22.7,
+        };
+
+        let mut expected = grid()
+            .into_iter()
+            .map(|(coords, index)| (distance(&centre, &coords), index))
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        expected.truncate(15);
+
+        assert_eq!(
+            tree.nearest(&centre, 15)
+                .into_iter()
+                .map(|(index, distance)| (distance, *index))
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn nearest_with_more_than_there_are() {
+        let tree = KdTree::new(vec![(
+            Coords {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            "Sol",
+        )]);
+
+        assert_eq!(
+            tree.nearest(
+                &Coords {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0
+                },
+                5
+            )
+            .len(),
+            1
+        );
+        assert!(tree
+            .nearest(
+                &Coords {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0
+                },
+                0
+            )
+            .is_empty());
+        assert_eq!(
+            tree.nearest(
+                &Coords {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0
+                },
+                usize::MAX
+            )
+            .len(),
+            1
+        );
+    }
+}
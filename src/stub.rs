@@ -9,6 +9,11 @@ pub struct System {
     pub(crate) population: u128,
     pub allegiance: String,
     pub government: String,
+    pub(crate) controlling_faction: Option<ControllingFaction>,
+    pub(crate) id64: Option<u64>,
+    pub(crate) economy: String,
+    pub(crate) security: String,
+    pub(crate) state: String,
 }
 
 impl<'system> domain::System<'system> for System {
@@ -40,6 +45,12 @@ impl<'system> domain::System<'system> for System {
             .collect::<Vec<Box<dyn domain::Faction>>>()
     }
 
+    fn controlling_faction(&self) -> Option<&dyn domain::ControllingFaction> {
+        self.controlling_faction
+            .as_ref()
+            .map(|faction| faction as &dyn domain::ControllingFaction)
+    }
+
     fn coordinates(&self) -> domain::Coords {
         self.coords
     }
@@ -47,25 +58,157 @@ impl<'system> domain::System<'system> for System {
     fn population(&self) -> u128 {
         self.population
     }
+
+    fn id64(&self) -> Option<u64> {
+        self.id64
+    }
+
+    fn economy(&self) -> &str {
+        &self.economy
+    }
+
+    fn security(&self) -> &str {
+        &self.security
+    }
+
+    fn state(&self) -> &str {
+        &self.state
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Station {
+    pub(crate) name: String,
     pub(crate) station_type: Option<String>,
+    pub(crate) distance_to_arrival: Option<f64>,
+    pub(crate) allegiance: String,
+    pub(crate) government: String,
+    pub(crate) economy: String,
+    pub(crate) has_market: bool,
+    pub(crate) has_shipyard: bool,
+    pub(crate) has_outfitting: bool,
+    pub(crate) other_services: Vec<String>,
 }
 
 impl domain::Station for Station {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
     fn station_type(&self) -> Option<&str> {
         self.station_type.as_deref()
     }
+
+    fn distance_to_arrival(&self) -> Option<f64> {
+        self.distance_to_arrival
+    }
+
+    fn allegiance(&self) -> &str {
+        &self.allegiance
+    }
+
+    fn government(&self) -> &str {
+        &self.government
+    }
+
+    fn economy(&self) -> &str {
+        &self.economy
+    }
+
+    fn has_market(&self) -> bool {
+        self.has_market
+    }
+
+    fn has_shipyard(&self) -> bool {
+        self.has_shipyard
+    }
+
+    fn has_outfitting(&self) -> bool {
+        self.has_outfitting
+    }
+
+    fn other_services(&self) -> Vec<&str> {
+        self.other_services.iter().map(String::as_str).collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ControllingFaction {
+    pub(crate) name: String,
+    pub(crate) allegiance: String,
+    pub(crate) government: String,
+    pub(crate) is_player: bool,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+impl domain::ControllingFaction for ControllingFaction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn allegiance(&self) -> &str {
+        &self.allegiance
+    }
+
+    fn government(&self) -> &str {
+        &self.government
+    }
+
+    fn is_player(&self) -> bool {
+        self.is_player
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Faction {
+    pub(crate) name: String,
+    pub(crate) allegiance: String,
+    pub(crate) government: String,
+    pub(crate) influence: f64,
+    pub(crate) state: String,
+    pub(crate) active_states: Vec<String>,
+    pub(crate) pending_states: Vec<String>,
+    pub(crate) recovering_states: Vec<String>,
+    pub(crate) happiness: String,
     pub(crate) is_player: bool,
 }
 
 impl crate::domain::Faction for Faction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn allegiance(&self) -> &str {
+        &self.allegiance
+    }
+
+    fn government(&self) -> &str {
+        &self.government
+    }
+
+    fn influence(&self) -> f64 {
+        self.influence
+    }
+
+    fn state(&self) -> &str {
+        &self.state
+    }
+
+    fn active_states(&self) -> Vec<&str> {
+        self.active_states.iter().map(String::as_str).collect()
+    }
+
+    fn pending_states(&self) -> Vec<&str> {
+        self.pending_states.iter().map(String::as_str).collect()
+    }
+
+    fn recovering_states(&self) -> Vec<&str> {
+        self.recovering_states.iter().map(String::as_str).collect()
+    }
+
+    fn happiness(&self) -> &str {
+        &self.happiness
+    }
+
     fn is_player(&self) -> bool {
         self.is_player
     }